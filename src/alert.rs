@@ -0,0 +1,184 @@
+use crate::hook::{HookAction, HookRunner, HookSettings};
+use crate::metric;
+use crate::system::SystemInfo;
+use log::info;
+use serde::Deserialize;
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    pub metric: String,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    pub command: Option<String>,
+    pub fifo: Option<PathBuf>,
+}
+
+impl AlertRule {
+    fn is_breached(&self, value: f64) -> bool {
+        self.above.is_some_and(|above| value > above)
+            || self.below.is_some_and(|below| value < below)
+    }
+
+    fn hooks(&self) -> Vec<HookAction> {
+        let mut hooks = vec![];
+        if let Some(command) = &self.command {
+            hooks.push(HookAction::Command(command.clone()));
+        }
+        if let Some(fifo) = &self.fifo {
+            hooks.push(HookAction::Fifo(fifo.clone()));
+        }
+        hooks
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl fmt::Display for AlertState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertState::Firing => write!(f, "firing"),
+            AlertState::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+pub struct AlertEvent {
+    pub name: String,
+    pub metric: String,
+    pub value: f64,
+    pub state: AlertState,
+}
+
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    firing: Vec<bool>,
+    hooks: HookRunner,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>, hook_settings: HookSettings) -> Self {
+        Self {
+            firing: vec![false; rules.len()],
+            rules,
+            hooks: HookRunner::new(hook_settings),
+        }
+    }
+
+    /// Swaps in reloaded rules. A rule that keeps its name keeps firing
+    /// without its hooks running again. Returns the names of the rules that
+    /// were firing and are gone now.
+    pub fn reconfigure(
+        &mut self,
+        rules: Vec<AlertRule>,
+        hook_settings: HookSettings,
+    ) -> Vec<String> {
+        let was_firing = self
+            .firing()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        self.firing = rules
+            .iter()
+            .map(|rule| was_firing.contains(&rule.name))
+            .collect();
+        self.rules = rules;
+        self.hooks = HookRunner::new(hook_settings);
+        was_firing
+            .into_iter()
+            .filter(|name| !self.rules.iter().any(|rule| rule.name == *name))
+            .collect()
+    }
+
+    /// The names of the rules currently firing.
+    pub fn firing(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .zip(&self.firing)
+            .filter(|(_, firing)| **firing)
+            .map(|(rule, _)| rule.name.as_str())
+            .collect()
+    }
+
+    /// Checks every rule against the latest readings, runs the hooks of any
+    /// rule that changed state and returns those changes.
+    pub fn evaluate(&mut self, system_info: &SystemInfo) -> Vec<AlertEvent> {
+        let mut events = vec![];
+        for (rule, firing) in self.rules.iter().zip(self.firing.iter_mut()) {
+            let value = match metric::lookup(system_info, &rule.metric) {
+                Some(value) => value,
+                None => continue,
+            };
+            if rule.is_breached(value) == *firing {
+                continue;
+            }
+            *firing = !*firing;
+
+            let event = AlertEvent {
+                name: rule.name.clone(),
+                metric: rule.metric.clone(),
+                value,
+                state: if *firing {
+                    AlertState::Firing
+                } else {
+                    AlertState::Resolved
+                },
+            };
+            info!(
+                "Alert '{}' {} ({} = {})",
+                event.name, event.state, event.metric, event.value
+            );
+            for hook in rule.hooks() {
+                self.hooks.run(&hook, &event);
+            }
+            events.push(event);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, above: f64) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            metric: "cpu.usage".to_string(),
+            above: Some(above),
+            below: None,
+            command: None,
+            fifo: None,
+        }
+    }
+
+    #[test]
+    fn reload_keeps_firing_rules_quiet() {
+        let mut system_info = SystemInfo::default();
+        system_info.cpu_usage = 90;
+        let mut alerts = Alerts::new(
+            vec![rule("busy", 80.), rule("gone", 50.)],
+            HookSettings::default(),
+        );
+        assert_eq!(alerts.evaluate(&system_info).len(), 2);
+
+        let removed = alerts.reconfigure(
+            vec![rule("busy", 70.), rule("new", 85.)],
+            HookSettings::default(),
+        );
+        assert_eq!(removed, vec!["gone".to_string()]);
+        assert_eq!(alerts.firing(), vec!["busy"]);
+
+        let events = alerts.evaluate(&system_info);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name, "new");
+        assert_eq!(events[0].state, AlertState::Firing);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::config::{Config, Overrides};
+use crate::headless;
+use crate::metric;
+use crate::system::SystemInfo;
+use crate::template::{Part, Template};
+use serde::Deserialize;
+use serde_json::json;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+const DEFAULT_TEMPLATE: &str = "CPU {cpu.usage:.0}% {cpu.temperature|temp:.0} | \
+                                GPU {gpu.utilization}% {gpu.temperature|temp:.0} \
+                                {gpu.power.draw|power:.0}";
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarSettings {
+    pub template: Template,
+    /// How often to take readings and print a line, `refresh.interval_ms`
+    /// doesn't apply. Bars redraw on every line.
+    pub interval_ms: u64,
+    pub warning_color: String,
+    pub critical_color: String,
+    pub threshold: Vec<Threshold>,
+}
+
+impl Default for BarSettings {
+    fn default() -> Self {
+        Self {
+            template: Template::try_from(DEFAULT_TEMPLATE).expect("Default template is valid"),
+            interval_ms: 1000,
+            warning_color: "#ffb86c".to_string(),
+            critical_color: "#ff5555".to_string(),
+            threshold: vec![
+                Threshold::new("cpu.temperature", 70., 85.),
+                Threshold::new("gpu.temperature", 75., 85.),
+            ],
+        }
+    }
+}
+
+impl BarSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval_ms == 0 {
+            return Err("bar.interval_ms must be at least 1".to_string());
+        }
+        for threshold in &self.threshold {
+            if !metric::is_known(&threshold.metric) {
+                return Err(format!(
+                    "bar threshold watches unknown metric '{}', expected one of: {}",
+                    threshold.metric,
+                    metric::names().join(", ")
+                ));
+            }
+            if threshold.warning.is_none() && threshold.critical.is_none() {
+                return Err(format!(
+                    "bar threshold for '{}' needs a `warning` or `critical` value",
+                    threshold.metric
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn level(&self, metric: &str, value: Option<f64>) -> Level {
+        let (card, name) = metric::resolve(metric);
+        let watching = |card: Option<usize>| {
+            self.threshold
+                .iter()
+                .find(|t| metric::resolve(&t.metric) == (card, name.clone()))
+        };
+        // A threshold for the card comes before one for every card.
+        let threshold = card
+            .and_then(|card| watching(Some(card)))
+            .or_else(|| watching(None));
+        let (threshold, value) = match (threshold, value) {
+            (Some(threshold), Some(value)) => (threshold, value),
+            _ => return Level::Normal,
+        };
+        if threshold.critical.is_some_and(|critical| value >= critical) {
+            Level::Critical
+        } else if threshold.warning.is_some_and(|warning| value >= warning) {
+            Level::Warning
+        } else {
+            Level::Normal
+        }
+    }
+
+    fn color(&self, level: Level) -> Option<&str> {
+        match level {
+            Level::Normal => None,
+            Level::Warning => Some(&self.warning_color),
+            Level::Critical => Some(&self.critical_color),
+        }
+    }
+}
+
+/// Colours a metric's value once it reaches `warning` or `critical`.
+/// `gpu.temperature` covers every card's, `gpu.1.temperature` just the one.
+/// Values are in the units readings are collected in, so temperatures are
+/// Celsius even with `units.temperature = "fahrenheit"`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub metric: String,
+    pub warning: Option<f64>,
+    pub critical: Option<f64>,
+}
+
+impl Threshold {
+    fn new(metric: &str, warning: f64, critical: f64) -> Self {
+        Self {
+            metric: metric.to_string(),
+            warning: Some(warning),
+            critical: Some(critical),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Level {
+    fn class(&self) -> &'static str {
+        match self {
+            Level::Normal => "normal",
+            Level::Warning => "warning",
+            Level::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// One JSON object per line, for a `custom` module with
+    /// `"return-type": "json"`.
+    Waybar,
+    /// The i3bar protocol, for `status_command`.
+    I3bar,
+    /// `#[fg=...]` styled lines, for `#(...)` in `status-right`.
+    Tmux,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "waybar" => Ok(Format::Waybar),
+            "i3bar" => Ok(Format::I3bar),
+            "tmux" => Ok(Format::Tmux),
+            _ => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+/// Prints a status line every `bar.interval_ms` until the bar goes away
+/// or SIGINT or SIGTERM. GTK is never initialised, and unlike headless mode
+/// nothing is exported, published or alerted on, so a bar can run next to
+/// the dashboard. Readings are only taken when a line is due.
+pub fn run(config_path: &Path, overrides: &Overrides, format: Format) {
+    let mut config = headless::load(config_path, overrides);
+    headless::stop_on_signals();
+    let mut modified = headless::modified_time(config_path);
+    let mut system_info = SystemInfo::new(&config);
+    system_info.start_energy_meter(&config.energy);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut first = true;
+    // i3bar takes a header, then an endless array.
+    let header = if format == Format::I3bar {
+        writeln!(out, "{}\n[", json!({ "version": 1 })).and_then(|()| out.flush())
+    } else {
+        Ok(())
+    };
+    if header.is_err() {
+        return;
+    }
+    loop {
+        system_info.update();
+        let line = render(&system_info, &config, format);
+        // Every element of the array but the first follows a comma.
+        let separator = if format == Format::I3bar && !first {
+            ","
+        } else {
+            ""
+        };
+        first = false;
+        if writeln!(out, "{}{}", separator, line)
+            .and_then(|()| out.flush())
+            .is_err()
+        {
+            // The bar exited or was reloaded.
+            return;
+        }
+        if !headless::sleep(Duration::from_millis(config.bar.interval_ms)) {
+            return;
+        }
+        if let Some(reloaded) = headless::reload(config_path, overrides, &mut modified) {
+            system_info.configure(&reloaded);
+            config = reloaded;
+        }
+    }
+}
+
+fn render(system_info: &SystemInfo, config: &Config, format: Format) -> String {
+    let settings = &config.bar;
+    let mut text = String::new();
+    let mut worst = Level::Normal;
+    for part in settings.template.parts() {
+        match part {
+            Part::Text(literal) => text.push_str(&escape(literal, format)),
+            Part::Field(field) => {
+                let value = metric::lookup(system_info, &field.metric);
+                let level = settings.level(&field.metric, value);
+                worst = worst.max(level);
+                let shown = escape(&field.show(system_info, &config.units), format);
+                match settings.color(level) {
+                    Some(color) => text.push_str(&colour(&shown, color, format)),
+                    None => text.push_str(&shown),
+                }
+            }
+        }
+    }
+    match format {
+        Format::Waybar => json!({ "text": text, "class": worst.class() }).to_string(),
+        Format::I3bar => json!([{
+            "full_text": text,
+            "markup": "pango",
+            "urgent": worst == Level::Critical,
+        }])
+        .to_string(),
+        Format::Tmux => text,
+    }
+}
+
+fn colour(text: &str, color: &str, format: Format) -> String {
+    match format {
+        Format::Waybar | Format::I3bar => format!(
+            "<span foreground='{}'>{}</span>",
+            escape(color, format),
+            text
+        ),
+        Format::Tmux => format!("#[fg={}]{}#[default]", color, text),
+    }
+}
+
+/// Keeps text from being read as markup: Pango for waybar and i3bar, `#`
+/// sequences for tmux.
+fn escape(text: &str, format: Format) -> String {
+    match format {
+        Format::Waybar | Format::I3bar => text
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('\'', "&apos;")
+            .replace('"', "&quot;"),
+        Format::Tmux => text.replace('#', "##"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::GPUInfo;
+
+    fn config(template: &str) -> Config {
+        let mut config = Config::default();
+        config.bar.template = Template::try_from(template).unwrap();
+        config
+    }
+
+    fn system_info(cpu_temp: u8, gpu_temps: &[u8]) -> SystemInfo {
+        let mut system_info = SystemInfo::default();
+        system_info.cpu_usage = 12;
+        system_info.cpu_temp = cpu_temp;
+        system_info.gpus = gpu_temps
+            .iter()
+            .map(|&temperature| GPUInfo {
+                temperature: Some(temperature),
+                ..GPUInfo::default()
+            })
+            .collect();
+        system_info.gpu_info = system_info.gpus.first().cloned().unwrap_or_default();
+        system_info
+    }
+
+    #[test]
+    fn levels_follow_the_thresholds() {
+        let settings = BarSettings::default();
+        assert_eq!(settings.level("cpu.temperature", Some(69.)), Level::Normal);
+        assert_eq!(settings.level("cpu.temperature", Some(70.)), Level::Warning);
+        assert_eq!(
+            settings.level("cpu.temperature", Some(85.)),
+            Level::Critical
+        );
+        assert_eq!(settings.level("cpu.temperature", None), Level::Normal);
+        assert_eq!(settings.level("cpu.usage", Some(100.)), Level::Normal);
+    }
+
+    #[test]
+    fn thresholds_match_aliases_and_cards() {
+        let mut settings = BarSettings::default();
+        assert_eq!(settings.level("gpu.temp", Some(80.)), Level::Warning);
+        assert_eq!(
+            settings.level("gpu.1.temperature", Some(80.)),
+            Level::Warning
+        );
+
+        settings
+            .threshold
+            .push(Threshold::new("gpu.1.temp", 50., 60.));
+        assert_eq!(
+            settings.level("gpu.1.temperature", Some(80.)),
+            Level::Critical
+        );
+        assert_eq!(
+            settings.level("gpu.0.temperature", Some(80.)),
+            Level::Warning
+        );
+    }
+
+    #[test]
+    fn waybar_gets_json_with_the_worst_level_as_class() {
+        let config = config("CPU {cpu.temperature|temp:.0} & GPU {gpu.1.temp|temp:.0}");
+        let line = render(&system_info(72, &[40, 90]), &config, Format::Waybar);
+        assert_eq!(
+            line,
+            json!({
+                "text": "CPU <span foreground='#ffb86c'>72C</span> &amp; \
+                         GPU <span foreground='#ff5555'>90C</span>",
+                "class": "critical",
+            })
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn i3bar_is_urgent_when_critical() {
+        let config = config("{cpu.usage}%");
+        let line = render(&system_info(40, &[]), &config, Format::I3bar);
+        assert_eq!(
+            line,
+            json!([{ "full_text": "12%", "markup": "pango", "urgent": false }]).to_string()
+        );
+        let line = render(&system_info(90, &[]), &config, Format::I3bar);
+        assert!(line.contains("\"urgent\":false"), "{}", line);
+
+        let config = self::config("{cpu.temperature}");
+        let line = render(&system_info(90, &[]), &config, Format::I3bar);
+        assert!(line.contains("\"urgent\":true"), "{}", line);
+    }
+
+    #[test]
+    fn tmux_gets_styled_text() {
+        let config = config("#{cpu.temperature}C");
+        let line = render(&system_info(75, &[]), &config, Format::Tmux);
+        assert_eq!(line, "###[fg=#ffb86c]75#[default]C");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            escape("<b>'a' & \"b\"</b>", Format::Waybar),
+            "&lt;b&gt;&apos;a&apos; &amp; &quot;b&quot;&lt;/b&gt;"
+        );
+        assert_eq!(escape("<b>", Format::I3bar), "&lt;b&gt;");
+        assert_eq!(escape("#[fg=red]", Format::Tmux), "##[fg=red]");
+    }
+
+    #[test]
+    fn the_default_template_uses_the_power_filter() {
+        let mut system_info = system_info(40, &[60]);
+        system_info.gpu_info.power_draw = Some(398.4);
+        let line = render(&system_info, &Config::default(), Format::Tmux);
+        assert!(line.ends_with(" 398 W"), "{}", line);
+    }
+}
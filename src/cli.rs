@@ -0,0 +1,413 @@
+use crate::bar;
+use crate::config::{self, Overrides, Panel};
+use crate::history::{parse_duration_ms, Selection};
+use crate::metric;
+use crate::snapshot::Format;
+use chrono::DateTime;
+use clap::{crate_version, App, Arg, ArgMatches, SubCommand};
+use log::LevelFilter;
+use std::path::PathBuf;
+
+pub struct Options {
+    pub config: PathBuf,
+    pub overrides: Overrides,
+    pub log_level: Option<LevelFilter>,
+    pub log_file: Option<PathBuf>,
+    pub fullscreen: bool,
+    pub screenshot: Option<PathBuf>,
+    /// Append every reading to this session file.
+    pub record: Option<PathBuf>,
+    /// The least time between two recorded readings.
+    pub record_interval_ms: u64,
+    /// Show a recorded session instead of live readings.
+    pub replay: Option<PathBuf>,
+    pub speed: f64,
+    /// Show readings streamed from agents at these addresses, with an
+    /// overview of them all when there is more than one.
+    pub connect: Vec<String>,
+    pub mode: Mode,
+    /// Everything after `--`, handed to GTK untouched.
+    pub gtk_args: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    Gui,
+    Headless,
+    Tui,
+    CheckConfig,
+    Snapshot(Format),
+    Bar(bar::Format),
+    Agent {
+        listen: String,
+    },
+    Export {
+        session: PathBuf,
+        output: Option<PathBuf>,
+        selection: Selection,
+    },
+}
+
+impl Options {
+    pub fn parse() -> Self {
+        Self::from_matches(&app().get_matches())
+    }
+
+    fn from_matches(matches: &ArgMatches) -> Self {
+        let mode = match matches.subcommand() {
+            ("check-config", _) => Mode::CheckConfig,
+            ("snapshot", Some(snapshot)) => Mode::Snapshot(
+                snapshot
+                    .value_of("format")
+                    .map(|f| f.parse::<Format>().expect("validated by clap"))
+                    .unwrap_or(Format::Json),
+            ),
+            ("bar", Some(bar)) => Mode::Bar(
+                bar.value_of("format")
+                    .expect("required by clap")
+                    .parse()
+                    .expect("validated by clap"),
+            ),
+            ("agent", Some(agent)) => Mode::Agent {
+                listen: agent
+                    .value_of("listen")
+                    .expect("defaulted by clap")
+                    .to_string(),
+            },
+            ("export", Some(export)) => Mode::Export {
+                session: PathBuf::from(export.value_of("session").expect("required by clap")),
+                output: export.value_of("output").map(PathBuf::from),
+                selection: Selection {
+                    columns: export
+                        .values_of("columns")
+                        .map(|columns| columns.map(String::from).collect())
+                        .unwrap_or_default(),
+                    since_ms: export.value_of("since").map(parse_time_ms),
+                    until_ms: export.value_of("until").map(parse_time_ms),
+                    last_ms: export
+                        .value_of("last")
+                        .map(|d| parse_duration_ms(d).expect("validated by clap")),
+                    resample_ms: export
+                        .value_of("resample")
+                        .map(|d| parse_duration_ms(d).expect("validated by clap")),
+                },
+            },
+            _ if matches.is_present("headless") => Mode::Headless,
+            _ if matches.is_present("tui") => Mode::Tui,
+            _ => Mode::Gui,
+        };
+
+        Self {
+            config: matches
+                .value_of("config")
+                .map(PathBuf::from)
+                .unwrap_or_else(config::default_path),
+            overrides: Overrides {
+                interval_ms: matches
+                    .value_of("interval")
+                    .map(|ms| ms.parse().expect("validated by clap")),
+                panels: matches.values_of("panels").map(|panels| {
+                    panels
+                        .map(|p| p.parse::<Panel>().expect("validated by clap"))
+                        .collect()
+                }),
+                root: matches.value_of("root").map(PathBuf::from),
+                recorded_commands: matches.value_of("commands").map(PathBuf::from),
+            },
+            log_level: matches
+                .value_of("log-level")
+                .map(|level| level.parse().expect("validated by clap")),
+            log_file: matches.value_of("log-file").map(PathBuf::from),
+            fullscreen: matches.is_present("fullscreen"),
+            screenshot: matches.value_of("screenshot").map(PathBuf::from),
+            record: matches.value_of("record").map(PathBuf::from),
+            record_interval_ms: matches
+                .value_of("record-interval")
+                .map(|d| parse_duration_ms(d).expect("validated by clap"))
+                .expect("defaulted by clap"),
+            replay: matches.value_of("replay").map(PathBuf::from),
+            speed: matches
+                .value_of("speed")
+                .map(|speed| speed.parse().expect("validated by clap"))
+                .unwrap_or(1.),
+            connect: matches
+                .values_of("connect")
+                .map(|addresses| addresses.map(String::from).collect())
+                .unwrap_or_default(),
+            mode,
+            gtk_args: matches
+                .values_of("gtk-args")
+                .map(|args| args.map(String::from).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("sys-dashboard")
+        .version(crate_version!())
+        .about("A system dashboard for CPU, memory and NVIDIA GPU metrics.")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .short("c")
+                .value_name("FILE")
+                .global(true)
+                .help("Config file to use instead of ~/.config/sys-dashboard/config.toml"),
+        )
+        .arg(
+            Arg::with_name("interval")
+                .long("interval")
+                .short("i")
+                .value_name("MS")
+                .validator(validate_interval)
+                .help("Refresh interval in milliseconds, overriding the config"),
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .global(true)
+                .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
+                .help("Minimum level of messages to log"),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .value_name("FILE")
+                .global(true)
+                .help("File to append log messages to"),
+        )
+        .arg(
+            Arg::with_name("fullscreen")
+                .long("fullscreen")
+                .short("f")
+                .conflicts_with("headless")
+                .help("Start with the dashboard window fullscreen"),
+        )
+        .arg(
+            Arg::with_name("panels")
+                .long("panels")
+                .short("p")
+                .value_name("PANELS")
+                .use_delimiter(true)
+                .possible_values(&["header", "cpu", "gpu"])
+                .help("Comma separated panels to show, overriding the config"),
+        )
+        .arg(
+            Arg::with_name("root")
+                .long("root")
+                .value_name("DIR")
+                .global(true)
+                .help("Read proc, sys and etc from under DIR instead of /"),
+        )
+        .arg(
+            Arg::with_name("commands")
+                .long("commands")
+                .value_name("DIR")
+                .global(true)
+                .help("Replay recorded nvidia-smi, lscpu, ... output from DIR"),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .help("Run the collectors, alerts and hooks without opening a window"),
+        )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .conflicts_with_all(&["headless", "fullscreen"])
+                .help("Draw the dashboard in the terminal instead of a window"),
+        )
+        .arg(
+            Arg::with_name("screenshot")
+                .long("screenshot")
+                .value_name("FILE")
+                .conflicts_with_all(&["headless", "tui"])
+                .help("Save the dashboard as a PNG once it has readings, then exit"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .value_name("FILE")
+                .conflicts_with("replay")
+                .help("Append readings to FILE as JSON lines"),
+        )
+        .arg(
+            Arg::with_name("record-interval")
+                .long("record-interval")
+                .value_name("DURATION")
+                .default_value("1s")
+                .validator(validate_duration)
+                .help("Record a reading every DURATION at most, 0 for every refresh"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("FILE")
+                .conflicts_with_all(&["headless", "tui"])
+                .help("Show the session recorded in FILE instead of live readings"),
+        )
+        .arg(
+            Arg::with_name("speed")
+                .long("speed")
+                .value_name("FACTOR")
+                .requires("replay")
+                .validator(validate_speed)
+                .help("Replay speed, e.g. 60 to play an hour in a minute [default: 1]"),
+        )
+        .arg(
+            Arg::with_name("connect")
+                .long("connect")
+                .value_name("HOST:PORT")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["headless", "tui", "replay"])
+                .help(
+                    "Show readings from a sys-dashboard agent instead of this machine, \
+                     repeat for an overview of several hosts. History, recordings, the \
+                     exporter, sinks, MQTT and D-Bus follow the first host",
+                ),
+        )
+        .arg(
+            Arg::with_name("gtk-args")
+                .multiple(true)
+                .last(true)
+                .value_name("GTK_ARGS")
+                .help("Arguments passed through to GTK"),
+        )
+        .subcommand(
+            SubCommand::with_name("check-config")
+                .about("Validate the config file and exit without opening a window"),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Print every metric once and exit")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["json"])
+                        .default_value("json"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bar")
+                .about("Print a status line for i3bar, waybar or tmux on every refresh")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .required(true)
+                        .possible_values(&["waybar", "i3bar", "tmux"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("agent")
+                .about("Run the collectors and stream readings to connecting dashboards")
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .default_value("127.0.0.1:7878")
+                        .help("Address to accept dashboards on, e.g. 0.0.0.0:7878"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Write metrics from a recorded session as CSV")
+                .arg(
+                    Arg::with_name("session")
+                        .required(true)
+                        .value_name("SESSION")
+                        .help("Session file written with --record"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("FILE")
+                        .help("Write to FILE instead of stdout"),
+                )
+                .arg(
+                    Arg::with_name("columns")
+                        .long("columns")
+                        .value_name("METRICS")
+                        .use_delimiter(true)
+                        .validator(validate_metric)
+                        .help("Comma separated metrics to include, e.g. gpu.power.draw [default: all]"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .value_name("TIME")
+                        .validator(validate_time)
+                        .help("Leave out samples before TIME (RFC 3339)"),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .value_name("TIME")
+                        .validator(validate_time)
+                        .help("Leave out samples after TIME (RFC 3339)"),
+                )
+                .arg(
+                    Arg::with_name("last")
+                        .long("last")
+                        .value_name("DURATION")
+                        .conflicts_with("since")
+                        .validator(validate_duration)
+                        .help("Only the last DURATION of the session, e.g. 30m"),
+                )
+                .arg(
+                    Arg::with_name("resample")
+                        .long("resample")
+                        .value_name("DURATION")
+                        .validator(validate_duration)
+                        .help("Average into one row per DURATION, e.g. 10s"),
+                ),
+        )
+}
+
+fn validate_metric(value: String) -> Result<(), String> {
+    if metric::is_known(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown metric, expected one of: {}",
+            metric::names().join(", ")
+        ))
+    }
+}
+
+fn validate_time(value: String) -> Result<(), String> {
+    DateTime::parse_from_rfc3339(&value)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_time_ms(value: &str) -> i64 {
+    DateTime::parse_from_rfc3339(value)
+        .expect("validated by clap")
+        .timestamp_millis()
+}
+
+fn validate_duration(value: String) -> Result<(), String> {
+    parse_duration_ms(&value).map(|_| ())
+}
+
+fn validate_speed(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0. && speed.is_finite() => Ok(()),
+        Ok(_) => Err("the speed must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn validate_interval(value: String) -> Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(ms) if ms >= 50 => Ok(()),
+        Ok(_) => Err("the interval must be at least 50ms".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
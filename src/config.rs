@@ -0,0 +1,357 @@
+use crate::alert::AlertRule;
+use crate::bar::BarSettings;
+use crate::control::ControlSettings;
+use crate::energy::EnergySettings;
+use crate::exporter::ExporterSettings;
+use crate::history::HistorySettings;
+use crate::hook::HookSettings;
+use crate::logging::LogSettings;
+use crate::metric;
+use crate::mqtt::MqttSettings;
+use crate::paths::HostPaths;
+use crate::remote::{AgentSettings, RemoteSettings};
+use crate::sink::SinkConfig;
+use crate::template::Template;
+use crate::units::Units;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub refresh: Refresh,
+    pub panels: Vec<Panel>,
+    pub units: Units,
+    pub labels: Labels,
+    pub sensors: Sensors,
+    pub paths: HostPaths,
+    pub commands: Commands,
+    pub theme: Theme,
+    pub hooks: HookSettings,
+    pub alert: Vec<AlertRule>,
+    pub sink: Vec<SinkConfig>,
+    pub mqtt: MqttSettings,
+    pub history: HistorySettings,
+    pub bar: BarSettings,
+    pub energy: EnergySettings,
+    /// Only read at startup, changing it needs a restart.
+    pub log: LogSettings,
+    /// Only read at startup, changing it needs a restart.
+    pub exporter: ExporterSettings,
+    /// Only read at startup, changing it needs a restart.
+    pub control: ControlSettings,
+    /// Only read at startup, changing it needs a restart.
+    pub agent: AgentSettings,
+    /// Only read at startup, changing it needs a restart.
+    pub remote: Vec<RemoteSettings>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            refresh: Refresh::default(),
+            panels: vec![Panel::Header, Panel::Cpu, Panel::Gpu],
+            units: Units::default(),
+            labels: Labels::default(),
+            sensors: Sensors::default(),
+            paths: HostPaths::default(),
+            commands: Commands::default(),
+            theme: Theme::default(),
+            hooks: HookSettings::default(),
+            alert: vec![],
+            sink: vec![],
+            mqtt: MqttSettings::default(),
+            history: HistorySettings::default(),
+            bar: BarSettings::default(),
+            energy: EnergySettings::default(),
+            log: LogSettings::default(),
+            exporter: ExporterSettings::default(),
+            control: ControlSettings::default(),
+            agent: AgentSettings::default(),
+            remote: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Refresh {
+    pub interval_ms: u64,
+    /// No shorter than `interval_ms`. An `--interval` longer than this
+    /// raises it to match.
+    pub gpu_interval_ms: u64,
+}
+
+impl Default for Refresh {
+    fn default() -> Self {
+        Self {
+            interval_ms: 100,
+            gpu_interval_ms: 100,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Panel {
+    Header,
+    Cpu,
+    Gpu,
+}
+
+impl FromStr for Panel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "header" => Ok(Panel::Header),
+            "cpu" => Ok(Panel::Cpu),
+            "gpu" => Ok(Panel::Gpu),
+            _ => Err(format!("unknown panel '{}'", s)),
+        }
+    }
+}
+
+/// What the views, the overview tiles and the TUI show for each reading.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Labels {
+    pub cpu_name: Template,
+    pub gpu_name: Template,
+    pub cpu_usage: Template,
+    pub cpu_temperature: Template,
+    pub ram_used: Template,
+    pub ram_total: Template,
+    pub cpu_freq: Template,
+    pub max_cpu_freq: Template,
+    pub gpu_usage: Template,
+    pub gpu_temperature: Template,
+    pub power_draw: Template,
+    pub power_limit: Template,
+    pub gpu_memory_used: Template,
+    pub gpu_memory_total: Template,
+    pub cpu_power: Template,
+    pub energy: Template,
+    /// Only shown when `energy.cost_per_kwh` is set.
+    pub energy_cost: Template,
+    pub tile_cpu: Template,
+    pub tile_ram: Template,
+    pub tile_gpu: Template,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        let template = |source: &str| Template::try_from(source).expect("Default label is valid");
+        Self {
+            cpu_name: template("CPU : {cpu.name:40}"),
+            gpu_name: template("GPU : {gpu.name:40}"),
+            cpu_usage: template("{cpu.usage:.0}%"),
+            cpu_temperature: template("{cpu.temperature|temp:>3.0}"),
+            ram_used: template("RAM Used : {mem.used|bytes}"),
+            ram_total: template("RAM Total : {mem.total|bytes}"),
+            cpu_freq: template("Avg CPU freq. : {cpu.freq.avg|round|freq}"),
+            max_cpu_freq: template("Max CPU freq. : {cpu.freq.max|round|freq}"),
+            gpu_usage: template("{gpu.utilization}%"),
+            gpu_temperature: template("{gpu.temperature|temp:>3.0}"),
+            power_draw: template("Power Draw : {gpu.power.draw|round|power}"),
+            power_limit: template("Power Limit : {gpu.power.limit|round|power}"),
+            gpu_memory_used: template("Memory Used : {gpu.memory.used|bytes}"),
+            gpu_memory_total: template("Memory Total : {gpu.memory.total|bytes}"),
+            cpu_power: template("CPU Power : {cpu.power.draw|round|power}"),
+            energy: template(
+                "Energy : {energy.session|energy} session, {energy.today|energy} today",
+            ),
+            energy_cost: template("Cost today : {energy.today.cost:.2}"),
+            tile_cpu: template("CPU : {cpu.usage:.0}% {cpu.temperature|temp:.0}"),
+            tile_ram: template("RAM : {mem.used|bytes}/{mem.total|bytes}"),
+            tile_gpu: template("GPU : {gpu.utilization}% {gpu.temperature|temp:.0}"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sensors {
+    pub cpu_thermal_zone: u32,
+    pub gpu_index: u32,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Commands {
+    /// Replay command outputs from this directory instead of running them.
+    pub recorded: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub css: PathBuf,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            css: PathBuf::from("custom.css"),
+        }
+    }
+}
+
+/// Settings given on the command line, which win over the config file and
+/// survive reloads of it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overrides {
+    pub interval_ms: Option<u64>,
+    pub panels: Option<Vec<Panel>>,
+    pub root: Option<PathBuf>,
+    pub recorded_commands: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Invalid(path, reason) => write!(f, "{}: {}", path.display(), reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads the config at `path`, falling back to the defaults if the file
+    /// does not exist.
+    pub fn load(path: &Path, overrides: &Overrides) -> Result<Self, ConfigError> {
+        let mut config = match read_to_string(path) {
+            Ok(contents) => {
+                toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+        };
+        if let Some(interval_ms) = overrides.interval_ms {
+            config.refresh.interval_ms = interval_ms;
+            // The GPU can't be polled more often than the refresh anyway.
+            config.refresh.gpu_interval_ms = config.refresh.gpu_interval_ms.max(interval_ms);
+        }
+        if let Some(panels) = &overrides.panels {
+            config.panels = panels.clone();
+        }
+        if let Some(root) = &overrides.root {
+            config.paths = HostPaths::rooted(root);
+        }
+        if let Some(dir) = &overrides.recorded_commands {
+            config.commands.recorded = Some(dir.clone());
+        }
+        config
+            .validate()
+            .map_err(|reason| ConfigError::Invalid(path.to_path_buf(), reason))?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.refresh.interval_ms < 50 {
+            return Err(format!(
+                "refresh.interval_ms must be at least 50, got {}",
+                self.refresh.interval_ms
+            ));
+        }
+        if self.refresh.gpu_interval_ms < self.refresh.interval_ms {
+            return Err(format!(
+                "refresh.gpu_interval_ms ({}) must not be shorter than refresh.interval_ms ({})",
+                self.refresh.gpu_interval_ms, self.refresh.interval_ms
+            ));
+        }
+        if self.panels.is_empty() {
+            return Err(
+                "panels must list at least one of \"header\", \"cpu\", \"gpu\"".to_string(),
+            );
+        }
+        for (i, panel) in self.panels.iter().enumerate() {
+            if self.panels[..i].contains(panel) {
+                return Err(format!("panel {:?} is listed more than once", panel));
+            }
+        }
+        if self.hooks.max_concurrent == 0 {
+            return Err("hooks.max_concurrent must be at least 1".to_string());
+        }
+        for rule in &self.alert {
+            if !metric::is_known(&rule.metric) {
+                return Err(format!(
+                    "alert '{}' watches unknown metric '{}', expected one of: {}",
+                    rule.name,
+                    rule.metric,
+                    metric::names().join(", ")
+                ));
+            }
+            if rule.above.is_none() && rule.below.is_none() {
+                return Err(format!(
+                    "alert '{}' needs an `above` or `below` threshold",
+                    rule.name
+                ));
+            }
+        }
+        self.mqtt.validate()?;
+        for sink in &self.sink {
+            sink.validate()?;
+        }
+        self.bar.validate()?;
+        self.energy.validate()?;
+        self.agent.validate()?;
+        for remote in &self.remote {
+            remote.validate()?;
+        }
+        Ok(())
+    }
+
+    pub fn shows(&self, panel: Panel) -> bool {
+        self.panels.contains(&panel)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/sys-dashboard/config.toml`, or `~/.config/...` when
+/// `XDG_CONFIG_HOME` is unset.
+pub fn default_path() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    base.join("sys-dashboard").join("config.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpu_interval_shorter_than_refresh_is_rejected() {
+        let config: Config =
+            toml::from_str("[refresh]\ninterval_ms = 500\ngpu_interval_ms = 100\n").unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn interval_override_raises_gpu_interval() {
+        let overrides = Overrides {
+            interval_ms: Some(500),
+            ..Overrides::default()
+        };
+        let config = Config::load(Path::new("/nonexistent/config.toml"), &overrides).unwrap();
+        assert_eq!(config.refresh.interval_ms, 500);
+        assert_eq!(config.refresh.gpu_interval_ms, 500);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::config::Panel;
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{self, Permissions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlSettings {
+    pub enabled: bool,
+    /// Defaults to `$XDG_RUNTIME_DIR/sys-dashboard.sock`.
+    pub socket: PathBuf,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        let dir = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir);
+        Self {
+            enabled: false,
+            socket: dir.join("sys-dashboard.sock"),
+        }
+    }
+}
+
+/// One line of JSON on the socket, such as `{"command": "pause"}`.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    /// The latest reading, in the `snapshot` subcommand's format. `host`
+    /// picks one of several `--connect` hosts by position, from 0.
+    Snapshot {
+        #[serde(default)]
+        host: usize,
+    },
+    /// The remembered readings, optionally narrowed down like `export`.
+    History {
+        #[serde(default)]
+        metrics: Vec<String>,
+        since_ms: Option<i64>,
+        until_ms: Option<i64>,
+        resample_ms: Option<u64>,
+    },
+    /// Shows these panels until the dashboard restarts.
+    Panels {
+        panels: Vec<Panel>,
+    },
+    Screenshot {
+        path: PathBuf,
+    },
+    Pause,
+    Resume,
+    /// Re-reads the config file now.
+    Reload,
+}
+
+/// A request waiting for the main loop to answer it.
+pub struct Call {
+    pub request: Request,
+    reply: Sender<Result<Value, String>>,
+}
+
+impl Call {
+    pub fn reply(self, result: Result<Value, String>) {
+        // The script may have hung up already, nothing to tell it then.
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Accepts JSON requests on a Unix socket, one per line, and answers each
+/// with a line of `{"ok": true, "result": ...}` or
+/// `{"ok": false, "error": ...}`. Requests are handed to `calls` because
+/// only the main loop may touch the dashboard.
+pub struct Control {
+    socket: PathBuf,
+}
+
+impl Control {
+    /// Binds the socket, or returns `None` when the API is disabled or the
+    /// socket can't be bound. Neither stops the dashboard.
+    pub fn start(settings: &ControlSettings, calls: glib::Sender<Call>) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        let socket = &settings.socket;
+        // Left behind by a dashboard that didn't shut down cleanly, unless
+        // another one is still answering on it. Anything but a socket is
+        // left alone and binding fails instead.
+        let stale = fs::symlink_metadata(socket).is_ok_and(|m| m.file_type().is_socket())
+            && UnixStream::connect(socket).is_err();
+        if stale {
+            let _ = fs::remove_file(socket);
+        }
+        let listener = match UnixListener::bind(socket) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Cannot listen on {}: {}", socket.display(), e);
+                return None;
+            }
+        };
+        // Anyone who can connect can take screenshots anywhere we can write.
+        if let Err(e) = fs::set_permissions(socket, Permissions::from_mode(0o600)) {
+            warn!("Cannot restrict {}: {}", socket.display(), e);
+        }
+        info!("Accepting control requests on {}", socket.display());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let calls = calls.clone();
+                        thread::spawn(move || serve(stream, calls));
+                    }
+                    Err(e) => warn!("Cannot accept control connection: {}", e),
+                }
+            }
+        });
+        Some(Self {
+            socket: socket.clone(),
+        })
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+fn serve(stream: UnixStream, calls: glib::Sender<Call>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            warn!("Cannot answer control connection: {}", e);
+            return;
+        }
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let (reply, answer) = channel();
+                if calls.send(Call { request, reply }).is_err() {
+                    return;
+                }
+                answer
+                    .recv()
+                    .unwrap_or_else(|_| Err("the dashboard is shutting down".to_string()))
+            }
+            Err(e) => Err(format!("bad request: {}", e)),
+        };
+        let response = match result {
+            Ok(result) => json!({ "ok": true, "result": result }),
+            Err(error) => json!({ "ok": false, "error": error }),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+    use std::time::{Duration, Instant};
+
+    fn settings(name: &str) -> ControlSettings {
+        let socket = env::temp_dir().join(format!("sys-dashboard-{}-{}.sock", name, process::id()));
+        let _ = fs::remove_file(&socket);
+        ControlSettings {
+            enabled: true,
+            socket,
+        }
+    }
+
+    /// Runs `client` against a control socket whose main loop answers
+    /// `pause` and nothing else.
+    fn with_control<T, F>(settings: &ControlSettings, client: F) -> (Option<Control>, T)
+    where
+        T: Send + 'static,
+        F: FnOnce(PathBuf) -> T + Send + 'static,
+    {
+        let context = glib::MainContext::new();
+        assert!(context.acquire());
+        context.with_thread_default(|| {
+            let (calls, requests) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            requests.attach(Some(&context), |call: Call| {
+                let result = match call.request {
+                    Request::Pause => Ok(json!(null)),
+                    _ => Err("unexpected".to_string()),
+                };
+                call.reply(result);
+                glib::Continue(true)
+            });
+            let control = Control::start(settings, calls);
+            let socket = settings.socket.clone();
+            let client = thread::spawn(move || client(socket));
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !client.is_finished() {
+                assert!(Instant::now() < deadline, "Timed out");
+                if !context.iteration(false) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+            (control, client.join().unwrap())
+        })
+    }
+
+    #[test]
+    fn answers_requests_on_a_private_socket() {
+        let settings = settings("control");
+        let (control, replies) = with_control(&settings, |socket| {
+            let mut stream = UnixStream::connect(socket).unwrap();
+            stream
+                .write_all(b"not json\n{\"command\": \"pause\"}\n")
+                .unwrap();
+            BufReader::new(stream)
+                .lines()
+                .take(2)
+                .map(|line| serde_json::from_str::<Value>(&line.unwrap()).unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(replies[0]["ok"], false);
+        assert!(replies[0]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("bad request"));
+        assert_eq!(replies[1], json!({ "ok": true, "result": null }));
+
+        let mode = fs::metadata(&settings.socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        drop(control);
+        assert!(!settings.socket.exists());
+    }
+
+    #[test]
+    fn leaves_anything_but_a_socket_alone() {
+        let settings = settings("control-file");
+        fs::write(&settings.socket, "not a socket").unwrap();
+        let (control, _) = with_control(&settings, |_| ());
+        assert!(control.is_none());
+        assert_eq!(
+            fs::read_to_string(&settings.socket).unwrap(),
+            "not a socket"
+        );
+        let _ = fs::remove_file(&settings.socket);
+    }
+}
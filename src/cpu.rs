@@ -1,6 +1,9 @@
-use crate::fmt::{create_label, Celcify, Name, Percentify};
+use crate::config::Config;
+use crate::error::Source;
+use crate::fmt::{create_label, show_problems, Percentify};
 use crate::gpu::update_usage;
 use crate::system::SystemInfo;
+use crate::units::{self, Temperature, Units};
 use cairo::{Context, Format, ImageSurface};
 use gdk::prelude::IsA;
 use gtk::{Align, BoxExt, GridExt, LabelExt, Orientation, StyleContextExt, Widget, WidgetExt};
@@ -15,6 +18,8 @@ pub struct CPUView {
     ram_total: gtk::Label,
     cpu_freq: gtk::Label,
     max_cpu_freq: gtk::Label,
+    cpu_power: gtk::Label,
+    status: gtk::Label,
 }
 
 impl CPUView {
@@ -29,6 +34,9 @@ impl CPUView {
         let ram_used = create_label("memory_used", Align::Start);
         let cpu_freq = create_label("cpu_freq", Align::Start);
         let max_cpu_freq = create_label("max_cpu_freq", Align::Start);
+        let cpu_power = create_label("cpu_power", Align::Start);
+        let status = create_label("status", Align::Start);
+        status.set_no_show_all(true);
 
         let arc_box = gtk::BoxBuilder::new()
             .orientation(Orientation::Vertical)
@@ -58,9 +66,11 @@ impl CPUView {
         container.attach(&ram_total, 2, 2, 1, 1);
         container.attach(&cpu_freq, 2, 3, 1, 1);
         container.attach(&max_cpu_freq, 2, 4, 1, 1);
+        container.attach(&cpu_power, 2, 5, 1, 1);
+        container.attach(&status, 0, 6, 3, 1);
 
-        cpu_usage.set_text(&*100u8.as_percentage());
-        cpu_temp.set_text(&*100u8.as_celcius());
+        cpu_usage.set_text(&100u8.as_percentage());
+        cpu_temp.set_text(&units::show(Temperature(100.), &Units::default()));
 
         Self {
             container,
@@ -72,38 +82,35 @@ impl CPUView {
             ram_total,
             cpu_freq,
             max_cpu_freq,
+            cpu_power,
+            status,
         }
     }
 
-    pub fn update(&self, system_info: &SystemInfo) {
+    pub fn update(&self, system_info: &SystemInfo, config: &Config) {
+        let labels = &config.labels;
+        let units = &config.units;
         self.cpu_name
-            .set_text(&system_info.cpu_name.trim().as_long_field_name("CPU"));
-        self.cpu_temp.set_text(&system_info.cpu_temp.as_celcius());
+            .set_text(&labels.cpu_name.render(system_info, units));
+        self.cpu_temp
+            .set_text(&labels.cpu_temperature.render(system_info, units));
         self.cpu_usage
-            .set_text(&(system_info.cpu_usage as u8).as_percentage());
+            .set_text(&labels.cpu_usage.render(system_info, units));
         update_usage(&self.cpu_usage_arc, system_info.cpu_usage as u8);
         self.container.queue_draw();
 
-        self.ram_used.set_text(
-            &system_info
-                .memory_info
-                .used_mib()
-                .as_field_name("RAM Used (MiB)"),
-        );
-        self.ram_total.set_text(
-            &system_info
-                .memory_info
-                .total_mib()
-                .as_field_name("RAM Total (MiB)"),
-        );
-        let avg: u32 =
-            (system_info.cpu_freq.iter().sum::<f32>() / system_info.cpu_freq.len() as f32) as u32;
+        self.ram_used
+            .set_text(&labels.ram_used.render(system_info, units));
+        self.ram_total
+            .set_text(&labels.ram_total.render(system_info, units));
         self.cpu_freq
-            .set_text(&avg.as_field_name("Avg CPU freq. (MHz)"));
-
-        let max = system_info.cpu_freq.last().unwrap();
+            .set_text(&labels.cpu_freq.render(system_info, units));
         self.max_cpu_freq
-            .set_text(&(*max as u32).as_field_name("Max CPU freq. (MHz)"));
+            .set_text(&labels.max_cpu_freq.render(system_info, units));
+        self.cpu_power
+            .set_text(&labels.cpu_power.render(system_info, units));
+
+        show_problems(&self.status, &system_info.problems(Source::CPU));
     }
 
     pub(super) fn widget(&self) -> &impl IsA<Widget> {
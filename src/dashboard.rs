@@ -1,37 +1,98 @@
 use gio::prelude::*;
 use gtk::prelude::*;
 
+use crate::alert::{AlertState, Alerts};
+use crate::cli::Options;
+use crate::config::{Config, ConfigError, Overrides, Panel};
+use crate::control::{Call, Control, Request};
 use crate::cpu::CPUView;
+use crate::dbus::{DbusObject, WindowRequest};
+use crate::export;
+use crate::exporter::Exporter;
 use crate::gpu::GPUView;
 use crate::header::HeaderView;
+use crate::history::{History, Selection};
+use crate::mqtt::Mqtt;
+use crate::overview::OverviewView;
+use crate::remote::Remote;
+use crate::replay::ReplayBar;
+use crate::screenshot;
+use crate::session::{Player, Recorder};
+use crate::sink::Sinks;
+use crate::snapshot::Snapshot;
 use crate::style::BASE_STYLE;
 use crate::system::SystemInfo;
-use std::cell::RefCell;
+use chrono::Local;
+use log::{error, info};
+use serde_json::json;
+use std::cell::{Cell, RefCell};
+use std::path::PathBuf;
+use std::process::exit;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
 pub(crate) struct Dashboard {
     app: gtk::Application,
+    config_path: PathBuf,
+    overrides: Overrides,
+    fullscreen: bool,
+    /// Save a screenshot once the first readings are on screen, then quit.
+    screenshot: Option<PathBuf>,
+    connect: Vec<String>,
+    gtk_args: Vec<String>,
+    recorder: Option<Recorder>,
+    player: Option<Player>,
 }
 
 impl Dashboard {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(
+        options: &Options,
+        recorder: Option<Recorder>,
+        player: Option<Player>,
+    ) -> Self {
         let app = gtk::Application::new(Some("org.dancrhorton.sys-dash.rs"), Default::default())
             .expect("Initialization failed...");
 
         app.connect_activate(|_| {});
 
-        Self { app }
+        Self {
+            app,
+            config_path: options.config.clone(),
+            overrides: options.overrides.clone(),
+            fullscreen: options.fullscreen,
+            screenshot: options.screenshot.clone(),
+            connect: options.connect.clone(),
+            gtk_args: options.gtk_args.clone(),
+            recorder,
+            player,
+        }
     }
 
     pub(crate) fn run(&mut self) {
+        let config_path = self.config_path.clone();
+        let overrides = self.overrides.clone();
+        let fullscreen = self.fullscreen;
+        let screenshot = self.screenshot.clone();
+        let connect = self.connect.clone();
+        // Startup only runs once, hand these over to it then.
+        let recorder = RefCell::new(self.recorder.take());
+        let player = RefCell::new(self.player.take());
         self.app.connect_startup(move |app| {
-            let provider = gtk::CssProvider::new();
-            provider.load_from_path("custom.css").unwrap_or_default();
+            let config = Config::load(&config_path, &overrides).unwrap_or_else(|e| {
+                error!("Using default configuration: {}", e);
+                Config::default()
+            });
+
+            let theme = gtk::CssProvider::new();
+            theme
+                .load_from_path(&config.theme.css.to_string_lossy())
+                .unwrap_or_default();
             gtk::StyleContext::add_provider_for_screen(
                 &gdk::Screen::get_default().expect("Error initializing gtk css provider."),
-                &provider,
+                &theme,
                 gtk::STYLE_PROVIDER_PRIORITY_USER,
             );
 
@@ -47,44 +108,481 @@ impl Dashboard {
                 gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
             );
 
+            let interval = Arc::new(AtomicU64::new(config.refresh.interval_ms));
             let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            let tick_interval = interval.clone();
             thread::spawn(move || loop {
                 let _ = tx.send(1);
-                thread::sleep(Duration::from_millis(100))
+                thread::sleep(Duration::from_millis(tick_interval.load(Ordering::Relaxed)))
             });
 
-            let system_info = RefCell::new(SystemInfo::new());
-            let widgets = Rc::new(Widgets::new(&app));
+            let player = player.borrow_mut().take().map(|p| Rc::new(RefCell::new(p)));
+            let widgets = Widgets::new(app, &connect, player.clone(), screenshot.is_some());
+            widgets.show_panels(&config);
+            if fullscreen && screenshot.is_none() {
+                widgets.mwnd.fullscreen();
+            }
+
+            let hosts = if connect.is_empty() {
+                // Nothing is collected while replaying.
+                let system_info = if player.is_some() {
+                    SystemInfo::default()
+                } else {
+                    let mut system_info = SystemInfo::new(&config);
+                    system_info.start_energy_meter(&config.energy);
+                    system_info
+                };
+                vec![Host::new(None, system_info, &config)]
+            } else {
+                connect
+                    .iter()
+                    .map(|address| {
+                        let settings = config
+                            .remote
+                            .iter()
+                            .find(|remote| remote.address == *address)
+                            .cloned();
+                        Host::new(
+                            Some(Remote::connect(address, settings)),
+                            SystemInfo::default(),
+                            &config,
+                        )
+                    })
+                    .collect()
+            };
+            let (window_requests, requested) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            let window = widgets.mwnd.clone();
+            requested.attach(None, move |request| {
+                match request {
+                    WindowRequest::Show => window.present(),
+                    WindowRequest::Hide => window.hide(),
+                }
+                glib::Continue(true)
+            });
+            let dbus = DbusObject::register(app, window_requests);
+
+            let state = Rc::new(State {
+                config_path: config_path.clone(),
+                overrides: RefCell::new(overrides.clone()),
+                paused: Cell::new(false),
+                hosts,
+                recorder: RefCell::new(recorder.borrow_mut().take()),
+                history: RefCell::new(if player.is_some() {
+                    History::unbounded()
+                } else {
+                    History::new(&config.history)
+                }),
+                player,
+                exporter: Exporter::start(&config.exporter),
+                sinks: RefCell::new(Sinks::new(config.sink.clone())),
+                mqtt: RefCell::new(Mqtt::new(&config.mqtt)),
+                dbus,
+                config: RefCell::new(config),
+                widgets,
+                interval,
+                theme,
+            });
+            if let Some(player) = &state.player {
+                let mut history = state.history.borrow_mut();
+                player
+                    .borrow_mut()
+                    .for_each_sample(|unix_ms, system_info| history.push(unix_ms, system_info));
+            }
+            add_actions(app, &state);
+            let monitor = watch_config(state.clone());
+
+            let (calls, requests) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            let control = RefCell::new(Control::start(&state.config.borrow().control, calls));
+            // Takes the socket away again when the dashboard quits.
+            app.connect_shutdown(move |_| drop(control.borrow_mut().take()));
+            let stopping = state.clone();
+            app.connect_shutdown(move |_| stop_energy_meters(&stopping));
+            // Quitting rather than dying, so shutdown saves the energy totals.
+            for signal in [libc::SIGINT, libc::SIGTERM] {
+                let app = app.clone();
+                glib::unix_signal_add_local(signal, move || {
+                    app.quit();
+                    glib::Continue(false)
+                });
+            }
+            let answering = state.clone();
+            requests.attach(None, move |call: Call| {
+                let result = handle(&answering, &call.request);
+                call.reply(result);
+                glib::Continue(true)
+            });
+            let app = app.clone();
+            let screenshot = screenshot.clone();
+            let mut ticks = 0;
 
             rx.attach(None, move |_| {
-                update(&system_info, &widgets);
+                // The monitor stops watching once dropped.
+                let _ = &monitor;
+                if !state.paused.get() {
+                    update(&state);
+                    notify(&app, &state);
+                    show_overview(&state);
+                }
+                ticks += 1;
+                // CPU usage needs two readings before it means anything.
+                if let (Some(path), 2) = (&screenshot, ticks) {
+                    if let Err(e) = screenshot::save(&state.widgets.view(), path) {
+                        eprintln!("{}", e);
+                        stop_energy_meters(&state);
+                        exit(1);
+                    }
+                    app.quit();
+                }
                 glib::Continue(true)
             });
         });
 
-        self.app.run(&Vec::new());
+        let mut args = vec![std::env::args().next().unwrap_or_default()];
+        args.extend(self.gtk_args.iter().cloned());
+        self.app.run(&args);
     }
 
     pub(crate) fn destroy(&self) {}
 }
 
-fn update(system_info: &RefCell<SystemInfo>, widgets: &Rc<Widgets>) {
-    system_info.borrow_mut().update();
-    let system_info = system_info.borrow();
-    widgets.header.update(&system_info);
-    widgets.gpu_view.update(&system_info);
-    widgets.cpu_view.update(&system_info);
+/// Saves the energy totals for the next run.
+fn stop_energy_meters(state: &State) {
+    for host in &state.hosts {
+        host.system_info.borrow_mut().stop_energy_meter();
+    }
+}
+
+struct State {
+    config: RefCell<Config>,
+    config_path: PathBuf,
+    /// The command line's, plus panels chosen over the control socket.
+    overrides: RefCell<Overrides>,
+    /// Stops updating over the control socket.
+    paused: Cell<bool>,
+    /// This machine, or every agent connected to, in `--connect` order.
+    hosts: Vec<Host>,
+    recorder: RefCell<Option<Recorder>>,
+    history: RefCell<History>,
+    player: Option<Rc<RefCell<Player>>>,
+    exporter: Option<Exporter>,
+    sinks: RefCell<Sinks>,
+    mqtt: RefCell<Option<Mqtt>>,
+    dbus: Option<DbusObject>,
+    widgets: Widgets,
+    interval: Arc<AtomicU64>,
+    theme: gtk::CssProvider,
+}
+
+struct Host {
+    remote: Option<Remote>,
+    system_info: RefCell<SystemInfo>,
+    alerts: RefCell<Alerts>,
+}
+
+impl Host {
+    fn new(remote: Option<Remote>, system_info: SystemInfo, config: &Config) -> Self {
+        Self {
+            remote,
+            system_info: RefCell::new(system_info),
+            alerts: RefCell::new(Alerts::new(config.alert.clone(), config.hooks)),
+        }
+    }
+}
+
+fn update(state: &State) {
+    match &state.player {
+        Some(player) => {
+            player.borrow_mut().advance();
+            let player = player.borrow();
+            show(state, 0, player.current());
+            if let Some(replay_bar) = &state.widgets.replay_bar {
+                replay_bar.update(&player);
+            }
+        }
+        None => {
+            for (index, host) in state.hosts.iter().enumerate() {
+                match &host.remote {
+                    Some(remote) => remote.update(&mut host.system_info.borrow_mut()),
+                    None => host.system_info.borrow_mut().update(),
+                }
+                let system_info = host.system_info.borrow();
+                // History, recordings, the exporter, sinks, MQTT and D-Bus
+                // follow the first host, the control socket's `snapshot`
+                // can ask for any of them.
+                if index == 0 {
+                    publish(state, &system_info);
+                }
+                show(state, index, &system_info);
+            }
+        }
+    }
+}
+
+fn publish(state: &State, system_info: &SystemInfo) {
+    let config = state.config.borrow();
+    state
+        .history
+        .borrow_mut()
+        .push(Local::now().timestamp_millis(), system_info);
+    if let Some(recorder) = &mut *state.recorder.borrow_mut() {
+        recorder.record(system_info);
+    }
+    if let Some(exporter) = &state.exporter {
+        exporter.publish(system_info, &config);
+    }
+    state.sinks.borrow_mut().publish(system_info);
+    if let Some(mqtt) = &mut *state.mqtt.borrow_mut() {
+        mqtt.publish(system_info);
+    }
+    if let Some(dbus) = &state.dbus {
+        dbus.publish(system_info);
+    }
+}
+
+fn show(state: &State, index: usize, system_info: &SystemInfo) {
+    state.widgets.hosts[index].update(system_info, &state.config.borrow());
+}
+
+/// Refreshes the tiles after the alerts were evaluated, so they show what
+/// is firing now.
+fn show_overview(state: &State) {
+    let overview = match &state.widgets.overview {
+        Some(overview) => overview,
+        None => return,
+    };
+    let config = state.config.borrow();
+    for (index, host) in state.hosts.iter().enumerate() {
+        let alerts = host.alerts.borrow();
+        overview.update(index, &host.system_info.borrow(), &alerts.firing(), &config);
+    }
+}
+
+fn add_actions(app: &gtk::Application, state: &Rc<State>) {
+    let file = gio::Menu::new();
+    file.append(Some("Export…"), Some("app.export"));
+    file.append(Some("Save screenshot…"), Some("app.screenshot"));
+    let menubar = gio::Menu::new();
+    menubar.append_submenu(Some("_File"), &file);
+    app.set_menubar(Some(&menubar));
+
+    let export = gio::SimpleAction::new("export", None);
+    let exporting = state.clone();
+    export.connect_activate(move |_, _| export_history(&exporting));
+    app.add_action(&export);
+    app.set_accels_for_action("app.export", &["<Primary>e"]);
+
+    let screenshot = gio::SimpleAction::new("screenshot", None);
+    let window = state.widgets.mwnd.clone();
+    let view = state.widgets.view();
+    screenshot.connect_activate(move |_, _| {
+        if let Some(path) = screenshot::ask(&window) {
+            match screenshot::save(&view, &path) {
+                Ok(()) => info!("Saved screenshot to {}", path.display()),
+                Err(e) => {
+                    error!("{}", e);
+                    export::show_error(&window, &e);
+                }
+            }
+        }
+    });
+    app.add_action(&screenshot);
+    app.set_accels_for_action("app.screenshot", &["<Primary>s", "Print"]);
+}
+
+fn export_history(state: &State) {
+    let window = &state.widgets.mwnd;
+    // The dialog runs its own main loop, which keeps updating the history,
+    // so only borrow it once the dialog is gone.
+    let (path, selection) = match export::ask(window) {
+        Some(choice) => choice,
+        None => return,
+    };
+    let written = export::write(&path, &state.history.borrow(), &selection);
+    match written {
+        Ok(rows) => info!("Exported {} rows to {}", rows, path.display()),
+        Err(e) => {
+            error!("{}", e);
+            export::show_error(window, &e);
+        }
+    }
+}
+
+fn notify(app: &gtk::Application, state: &State) {
+    // Alerts are about what's happening now, not what's being replayed.
+    if state.player.is_some() {
+        return;
+    }
+    for (index, host) in state.hosts.iter().enumerate() {
+        let system_info = host.system_info.borrow();
+        for event in host.alerts.borrow_mut().evaluate(&system_info) {
+            let id = notification_id(index, &event.name);
+            match event.state {
+                AlertState::Firing => {
+                    let title = if state.hosts.len() > 1 {
+                        format!("{} on {}", event.name, system_info.host)
+                    } else {
+                        event.name.clone()
+                    };
+                    let notification = gio::Notification::new(&title);
+                    notification.set_body(Some(&format!("{} is {}", event.metric, event.value)));
+                    app.send_notification(Some(&id), &notification);
+                }
+                AlertState::Resolved => app.withdraw_notification(&id),
+            }
+        }
+    }
+}
+
+fn notification_id(host: usize, rule: &str) -> String {
+    format!("alert-{}-{}", host, rule)
+}
+
+/// Re-reads the config whenever it changes on disk and applies it to the
+/// running dashboard. An invalid file is reported and otherwise ignored, so
+/// the last good config stays in effect.
+fn watch_config(state: Rc<State>) -> Option<gio::FileMonitor> {
+    let path = state.config_path.clone();
+    let file = gio::File::new_for_path(&path);
+    let monitor = match file.monitor_file(gio::FileMonitorFlags::NONE, None::<&gio::Cancellable>) {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            error!("Cannot watch {} for changes: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    monitor.connect_changed(move |_, _, _, event| match event {
+        gio::FileMonitorEvent::ChangesDoneHint
+        | gio::FileMonitorEvent::Created
+        | gio::FileMonitorEvent::Deleted => {
+            if let Err(e) = reload(&state) {
+                error!("Keeping previous configuration: {}", e);
+            }
+        }
+        _ => {}
+    });
+    Some(monitor)
+}
+
+fn reload(state: &State) -> Result<(), ConfigError> {
+    let config = Config::load(&state.config_path, &state.overrides.borrow())?;
+    info!("Reloaded {}", state.config_path.display());
+    apply_config(state, config);
+    Ok(())
+}
+
+/// Answers a request from the control socket.
+fn handle(state: &State, request: &Request) -> Result<serde_json::Value, String> {
+    match request {
+        Request::Snapshot { host } => {
+            let snapshot = match &state.player {
+                Some(player) => serde_json::to_value(Snapshot::new(player.borrow().current())),
+                None => {
+                    let host = state.hosts.get(*host).ok_or_else(|| {
+                        format!(
+                            "no host {}, the dashboard shows {}",
+                            host,
+                            state.hosts.len()
+                        )
+                    })?;
+                    serde_json::to_value(Snapshot::new(&host.system_info.borrow()))
+                }
+            };
+            snapshot.map_err(|e| e.to_string())
+        }
+        Request::History {
+            metrics,
+            since_ms,
+            until_ms,
+            resample_ms,
+        } => {
+            let selection = Selection {
+                columns: metrics.clone(),
+                since_ms: *since_ms,
+                until_ms: *until_ms,
+                last_ms: None,
+                resample_ms: *resample_ms,
+            };
+            let (columns, rows) = state.history.borrow().rows(&selection)?;
+            Ok(json!({ "columns": columns, "rows": rows }))
+        }
+        Request::Panels { panels } => {
+            let mut config = state.config.borrow().clone();
+            config.panels = panels.clone();
+            config.validate()?;
+            state.overrides.borrow_mut().panels = Some(panels.clone());
+            state.widgets.show_panels(&config);
+            *state.config.borrow_mut() = config;
+            Ok(json!(null))
+        }
+        Request::Screenshot { path } => {
+            screenshot::save(&state.widgets.view(), path)?;
+            info!("Saved screenshot to {}", path.display());
+            Ok(json!(null))
+        }
+        Request::Pause => {
+            state.paused.set(true);
+            Ok(json!(null))
+        }
+        Request::Resume => {
+            state.paused.set(false);
+            Ok(json!(null))
+        }
+        Request::Reload => reload(state)
+            .map(|()| json!(null))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn apply_config(state: &State, config: Config) {
+    state
+        .interval
+        .store(config.refresh.interval_ms, Ordering::Relaxed);
+    state
+        .theme
+        .load_from_path(&config.theme.css.to_string_lossy())
+        .unwrap_or_default();
+    for (index, host) in state.hosts.iter().enumerate() {
+        if host.remote.is_none() {
+            host.system_info.borrow_mut().configure(&config);
+        }
+        let removed = host
+            .alerts
+            .borrow_mut()
+            .reconfigure(config.alert.clone(), config.hooks);
+        // Nothing will resolve them now.
+        if let Some(app) = gio::Application::get_default() {
+            for name in removed {
+                app.withdraw_notification(&notification_id(index, &name));
+            }
+        }
+    }
+    if state.player.is_none() {
+        state.history.borrow_mut().configure(&config.history);
+    }
+    state.sinks.borrow_mut().reconfigure(config.sink.clone());
+    *state.mqtt.borrow_mut() = Mqtt::new(&config.mqtt);
+    state.widgets.show_panels(&config);
+    *state.config.borrow_mut() = config;
 }
 
 struct Widgets {
-    _mwnd: gtk::ApplicationWindow,
-    header: HeaderView,
-    gpu_view: GPUView,
-    cpu_view: CPUView,
+    mwnd: gtk::ApplicationWindow,
+    /// Holds the dashboard instead of `mwnd` with `--screenshot`, so
+    /// nothing is presented.
+    offscreen: Option<gtk::OffscreenWindow>,
+    hosts: Vec<HostView>,
+    overview: Option<OverviewView>,
+    replay_bar: Option<ReplayBar>,
 }
 
 impl Widgets {
-    fn new(app: &gtk::Application) -> Self {
+    fn new(
+        app: &gtk::Application,
+        addresses: &[String],
+        player: Option<Rc<RefCell<Player>>>,
+        offscreen: bool,
+    ) -> Self {
         let window = gtk::ApplicationWindow::new(app);
 
         window.set_title("System Dashboard");
@@ -92,6 +590,102 @@ impl Widgets {
         window.set_border_width(10);
         window.set_position(gtk::WindowPosition::Center);
 
+        // The overview when there is one, and a page per host.
+        let stack = gtk::StackBuilder::new()
+            .transition_type(gtk::StackTransitionType::Crossfade)
+            .build();
+        let overview = if addresses.len() > 1 {
+            Some(OverviewView::new(addresses))
+        } else {
+            None
+        };
+        if let Some(overview) = &overview {
+            stack.add_named(overview.widget(), "overview");
+            let selecting = stack.clone();
+            overview.connect_selected(move |index| {
+                selecting.set_visible_child_name(&host_page(index));
+            });
+        }
+
+        let hosts = (0..addresses.len().max(1))
+            .map(|index| {
+                let host = HostView::new();
+                if overview.is_some() {
+                    let back = gtk::Button::with_label("← Overview");
+                    back.set_halign(gtk::Align::Start);
+                    let returning = stack.clone();
+                    back.connect_clicked(move |_| returning.set_visible_child_name("overview"));
+                    host.container.pack_start(&back, false, false, 0);
+                    host.container.reorder_child(&back, 0);
+                }
+                stack.add_named(&host.container, &host_page(index));
+                host
+            })
+            .collect();
+
+        let main_view_box = gtk::BoxBuilder::new()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(12)
+            .build();
+        main_view_box.pack_start(&stack, true, true, 0);
+
+        let replay_bar = player.map(ReplayBar::new);
+        if let Some(replay_bar) = &replay_bar {
+            main_view_box.pack_end(replay_bar.widget(), false, false, 0);
+        }
+
+        // The application window stays, hidden, to keep the application
+        // running and parent its dialogs.
+        let offscreen = if offscreen {
+            let offscreen = gtk::OffscreenWindow::new();
+            offscreen.set_border_width(10);
+            offscreen.add(&main_view_box);
+            offscreen.show_all();
+            Some(offscreen)
+        } else {
+            window.add(&main_view_box);
+            window.show_all();
+            None
+        };
+
+        Self {
+            mwnd: window,
+            offscreen,
+            hosts,
+            overview,
+            replay_bar,
+        }
+    }
+
+    fn show_panels(&self, config: &Config) {
+        for host in &self.hosts {
+            host.show_panels(config);
+        }
+    }
+
+    /// What screenshots capture.
+    fn view(&self) -> gtk::Widget {
+        match &self.offscreen {
+            Some(offscreen) => offscreen.clone().upcast(),
+            None => self.mwnd.clone().upcast(),
+        }
+    }
+}
+
+fn host_page(index: usize) -> String {
+    format!("host-{}", index)
+}
+
+/// The full dashboard of one host.
+struct HostView {
+    container: gtk::Box,
+    header: HeaderView,
+    gpu_view: GPUView,
+    cpu_view: CPUView,
+}
+
+impl HostView {
+    fn new() -> Self {
         let header = HeaderView::new();
 
         let cpu_view = CPUView::new();
@@ -107,22 +701,33 @@ impl Widgets {
         widgets_grid.attach(cpu_view.widget(), 0, 0, 1, 1);
         widgets_grid.attach(gpu_view.widget(), 0, 1, 1, 1);
 
-        let main_view_box = gtk::BoxBuilder::new()
+        let container = gtk::BoxBuilder::new()
             .orientation(gtk::Orientation::Vertical)
             .spacing(12)
             .build();
 
-        main_view_box.pack_start(header.widget(), false, false, 0);
-        main_view_box.pack_start(&widgets_grid, false, false, 0);
-
-        window.add(&main_view_box);
-        window.show_all();
+        container.pack_start(header.widget(), false, false, 0);
+        container.pack_start(&widgets_grid, false, false, 0);
 
         Self {
-            _mwnd: window,
+            container,
             header,
             gpu_view,
             cpu_view,
         }
     }
+
+    fn update(&self, system_info: &SystemInfo, config: &Config) {
+        self.header.update(system_info, config);
+        self.gpu_view.update(system_info, config);
+        self.cpu_view.update(system_info, config);
+    }
+
+    fn show_panels(&self, config: &Config) {
+        self.header
+            .widget()
+            .set_visible(config.shows(Panel::Header));
+        self.cpu_view.widget().set_visible(config.shows(Panel::Cpu));
+        self.gpu_view.widget().set_visible(config.shows(Panel::Gpu));
+    }
 }
@@ -0,0 +1,357 @@
+use crate::metric;
+use crate::system::SystemInfo;
+use gio::ApplicationExt;
+use glib::translate::{from_glib_none, ToGlibPtr};
+use glib::{ToVariant, Variant, VariantDict};
+use log::{error, info, warn};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+const INTERFACE: &str = "org.dancrhorton.SysDash";
+
+/// Asked for over D-Bus, carried out by the main loop.
+pub enum WindowRequest {
+    Show,
+    Hide,
+}
+
+/// Every metric as a read-only property of `org.dancrhorton.SysDash` on the
+/// application's own object, `CpuUsage` for `cpu.usage` and so on, with
+/// `PropertiesChanged` signals as they change. Readings the hardware
+/// doesn't report are NaN, D-Bus has no null.
+pub struct DbusObject {
+    connection: gio::DBusConnection,
+    path: String,
+    values: Arc<Mutex<Vec<f64>>>,
+}
+
+impl DbusObject {
+    /// Registers the object, or returns `None` when the application isn't
+    /// on a bus or the object can't be registered. Neither stops the
+    /// dashboard.
+    pub fn register(app: &gtk::Application, requests: glib::Sender<WindowRequest>) -> Option<Self> {
+        let connection = app.get_dbus_connection()?;
+        let path = app.get_dbus_object_path()?.to_string();
+        Self::export(connection, path, requests)
+    }
+
+    fn export(
+        connection: gio::DBusConnection,
+        path: String,
+        requests: glib::Sender<WindowRequest>,
+    ) -> Option<Self> {
+        let node = gio::DBusNodeInfo::new_for_xml(&introspection())
+            .expect("Introspection XML is generated valid");
+        let interface = node
+            .lookup_interface(INTERFACE)
+            .expect("Introspection XML declares the interface");
+
+        let names = property_names();
+        let values = Arc::new(Mutex::new(vec![f64::NAN; names.len()]));
+        let read = values.clone();
+        // The callbacks must be Sync, though GLib only calls them on the
+        // main loop.
+        let requests = Mutex::new(requests);
+        let registered = connection.register_object(
+            &path,
+            &interface,
+            move |_, _, _, _, method, _, invocation| {
+                let request = match method {
+                    "Show" => WindowRequest::Show,
+                    _ => WindowRequest::Hide,
+                };
+                let _ = requests
+                    .lock()
+                    .expect("D-Bus request sender poisoned")
+                    .send(request);
+                invocation.return_value(None);
+            },
+            move |_, _, _, _, property| {
+                let values = read.lock().expect("D-Bus values poisoned");
+                let value = names
+                    .iter()
+                    .position(|name| name == property)
+                    .map_or(f64::NAN, |i| values[i]);
+                value.to_variant()
+            },
+            |_, _, _, _, _, _| false,
+        );
+        match registered {
+            Ok(_) => {
+                info!("Publishing metrics on D-Bus at {}", path);
+                Some(Self {
+                    connection,
+                    path,
+                    values,
+                })
+            }
+            Err(e) => {
+                error!("Cannot publish metrics on D-Bus: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Keeps the latest readings for Get and signals the ones that changed.
+    pub fn publish(&self, system_info: &SystemInfo) {
+        let changed = VariantDict::new(None);
+        let mut any = false;
+        {
+            let mut values = self.values.lock().expect("D-Bus values poisoned");
+            for ((name, value), metric) in property_names()
+                .into_iter()
+                .zip(values.iter_mut())
+                .zip(metric::collect(system_info))
+            {
+                let latest = metric.value.unwrap_or(f64::NAN);
+                // Bitwise, so a reading that stays unavailable isn't a change.
+                if latest.to_bits() != value.to_bits() {
+                    *value = latest;
+                    changed.insert_value(&name, &latest.to_variant());
+                    any = true;
+                }
+            }
+        }
+        if !any {
+            return;
+        }
+        let body = tuple(&[INTERFACE.to_variant(), changed.end(), no_strings()]);
+        if let Err(e) = self.connection.emit_signal(
+            None,
+            &self.path,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            Some(&body),
+        ) {
+            warn!("Cannot signal changed metrics on D-Bus: {}", e);
+        }
+    }
+}
+
+/// glib's bindings only build tuples of Rust types, not of variants.
+fn tuple(children: &[Variant]) -> Variant {
+    let children = children
+        .iter()
+        .map(|child| child.to_glib_none().0)
+        .collect::<Vec<*mut glib_sys::GVariant>>();
+    unsafe {
+        from_glib_none(glib_sys::g_variant_new_tuple(
+            children.as_ptr(),
+            children.len(),
+        ))
+    }
+}
+
+/// An empty `as`.
+fn no_strings() -> Variant {
+    unsafe { from_glib_none(glib_sys::g_variant_new_strv(ptr::null(), 0)) }
+}
+
+/// `cpu.freq.avg` becomes `CpuFreqAvg`.
+fn property_names() -> Vec<String> {
+    metric::names()
+        .into_iter()
+        .map(|name| {
+            name.split('.')
+                .map(|part| {
+                    let mut chars = part.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn introspection() -> String {
+    let properties = property_names()
+        .iter()
+        .map(|name| format!(r#"    <property name="{}" type="d" access="read"/>"#, name))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"<node>
+  <interface name="{}">
+    <method name="Show"/>
+    <method name="Hide"/>
+{}
+  </interface>
+</node>"#,
+        INTERFACE, properties
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const PATH: &str = "/org/dancrhorton/sys_dash/rs";
+
+    /// A private session bus, stopped when dropped.
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("dbus-daemon runs");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            Self {
+                daemon,
+                address: address.trim().to_string(),
+            }
+        }
+
+        fn connect(&self) -> gio::DBusConnection {
+            gio::DBusConnection::new_for_address_sync(
+                &self.address,
+                gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                    | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                None,
+                None::<&gio::Cancellable>,
+            )
+            .expect("Connects to the private bus")
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    /// Runs the main loop until `ready` has an answer.
+    fn wait_for<T>(mut ready: impl FnMut() -> Option<T>) -> T {
+        let context = glib::MainContext::ref_thread_default();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(answer) = ready() {
+                return answer;
+            }
+            assert!(Instant::now() < deadline, "Timed out on the bus");
+            if !context.iteration(false) {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    /// The printed reply of a method call on the dashboard's object.
+    fn call(
+        client: &gio::DBusConnection,
+        dashboard: &str,
+        interface: &str,
+        method: &str,
+        parameters: Option<Variant>,
+    ) -> String {
+        let (tx, rx) = mpsc::channel();
+        client.call(
+            Some(dashboard),
+            PATH,
+            interface,
+            method,
+            parameters.as_ref(),
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            None::<&gio::Cancellable>,
+            move |reply| {
+                let _ = tx.send(reply.map(|reply| reply.to_string()));
+            },
+        );
+        wait_for(|| rx.try_recv().ok()).expect("The call succeeds")
+    }
+
+    fn get(client: &gio::DBusConnection, dashboard: &str, property: &str) -> String {
+        let parameters = tuple(&[INTERFACE.to_variant(), property.to_variant()]);
+        call(
+            client,
+            dashboard,
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            Some(parameters),
+        )
+    }
+
+    #[test]
+    fn properties_signals_and_methods_on_a_private_bus() {
+        // The bus calls and the window requests are dispatched on this
+        // thread's context, which the test iterates itself.
+        let context = glib::MainContext::new();
+        assert!(context.acquire());
+        context.with_thread_default(|| {
+            let bus = Bus::start();
+            let connection = bus.connect();
+            let dashboard = connection.get_unique_name().unwrap().to_string();
+            let client = bus.connect();
+
+            let (requests, requested) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            let received = Rc::new(RefCell::new(Vec::new()));
+            let sink = received.clone();
+            requested.attach(Some(&context), move |request| {
+                sink.borrow_mut().push(match request {
+                    WindowRequest::Show => "Show",
+                    WindowRequest::Hide => "Hide",
+                });
+                glib::Continue(true)
+            });
+            let object = DbusObject::export(connection, PATH.to_string(), requests).unwrap();
+
+            let signals = Rc::new(RefCell::new(Vec::new()));
+            let sink = signals.clone();
+            client.signal_subscribe(
+                Some(&dashboard),
+                Some("org.freedesktop.DBus.Properties"),
+                Some("PropertiesChanged"),
+                Some(PATH),
+                None,
+                gio::DBusSignalFlags::NONE,
+                move |_, _, _, _, _, body| sink.borrow_mut().push(body.to_string()),
+            );
+
+            // Nothing published yet. The round trip also settles the match rule.
+            assert_eq!(get(&client, &dashboard, "CpuUsage"), "(<nan>,)");
+
+            let mut system_info = SystemInfo::default();
+            system_info.cpu_usage = 40;
+            object.publish(&system_info);
+            let body = wait_for(|| signals.borrow_mut().pop());
+            assert!(
+                body.starts_with("('org.dancrhorton.SysDash', {"),
+                "{}",
+                body
+            );
+            assert!(body.contains("'CpuUsage': <40.0>"), "{}", body);
+            assert!(body.ends_with("}, [])"), "{}", body);
+            assert_eq!(get(&client, &dashboard, "CpuUsage"), "(<40.0>,)");
+
+            // Unchanged readings aren't signalled again.
+            object.publish(&system_info);
+            assert_eq!(get(&client, &dashboard, "CpuUsage"), "(<40.0>,)");
+            assert!(signals.borrow().is_empty());
+
+            assert_eq!(call(&client, &dashboard, INTERFACE, "Show", None), "()");
+            assert_eq!(call(&client, &dashboard, INTERFACE, "Hide", None), "()");
+            wait_for(|| Some(()).filter(|_| received.borrow().len() == 2));
+            assert_eq!(*received.borrow(), ["Show", "Hide"]);
+        });
+    }
+}
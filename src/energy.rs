@@ -0,0 +1,361 @@
+use crate::logging;
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+/// How often the totals are written out, and how often a dashboard that
+/// isn't keeping the accounts picks up the latest ones.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// Days of totals kept in the file.
+const KEEP_DAYS: usize = 366;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnergySettings {
+    /// Only read at startup, changing it needs a restart.
+    pub enabled: bool,
+    /// Price of a kWh in any currency, to show what today cost.
+    pub cost_per_kwh: Option<f64>,
+    /// Where the daily totals are kept between runs. Defaults to
+    /// `$XDG_STATE_HOME/sys-dashboard/energy.json`. Only read at startup,
+    /// changing it needs a restart.
+    pub file: PathBuf,
+}
+
+impl Default for EnergySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cost_per_kwh: None,
+            file: logging::state_dir().join("energy.json"),
+        }
+    }
+}
+
+impl EnergySettings {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.cost_per_kwh {
+            Some(cost) if !cost.is_finite() || cost < 0. => Err(format!(
+                "energy.cost_per_kwh must be a number of at least 0, got {}",
+                cost
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What the meter has counted so far, `None` until there is something to
+/// count.
+#[derive(Default, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct EnergyReading {
+    pub session_wh: Option<f64>,
+    pub today_wh: Option<f64>,
+    /// Today's energy at `energy.cost_per_kwh`.
+    pub today_cost: Option<f64>,
+}
+
+/// Watt-hours used on each local date, as kept in the file.
+#[derive(Default, Serialize, Deserialize)]
+struct Totals {
+    days: BTreeMap<String, f64>,
+}
+
+/// Adds up CPU and GPU power draw over time. Several dashboards may run on
+/// one machine at once, so only the first to start keeps the daily
+/// accounts, marked by a `.lock` file next to them holding its pid. The
+/// others count their own session and read today's total from the file,
+/// taking over if the one keeping it goes away.
+pub struct EnergyMeter {
+    file: PathBuf,
+    lock: PathBuf,
+    owner: bool,
+    totals: Totals,
+    session_wh: Option<f64>,
+    last_sample: Option<Instant>,
+    last_saved: Instant,
+}
+
+impl EnergyMeter {
+    pub fn start(settings: &EnergySettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        let file = settings.file.clone();
+        let lock = beside(&file, ".lock");
+        let owner = acquire(&lock);
+        if owner {
+            info!("Keeping energy totals in {}", file.display());
+        } else {
+            info!(
+                "Another dashboard keeps the energy totals in {}, only reading them",
+                file.display()
+            );
+        }
+        Some(Self {
+            totals: load(&file),
+            file,
+            lock,
+            owner,
+            session_wh: None,
+            last_sample: None,
+            last_saved: Instant::now(),
+        })
+    }
+
+    /// Counts `watts` as drawn since the last call. An interval without a
+    /// reading isn't counted.
+    pub fn add(&mut self, watts: Option<f64>) {
+        self.add_at(watts, Instant::now(), Local::now());
+    }
+
+    /// `add` at `now`, which is `local` on the wall clock.
+    fn add_at(&mut self, watts: Option<f64>, now: Instant, local: DateTime<Local>) {
+        if let (Some(last), Some(watts)) = (self.last_sample, watts) {
+            let elapsed = now.duration_since(last);
+            *self.session_wh.get_or_insert(0.) += watts * elapsed.as_secs_f64() / 3600.;
+            if self.owner {
+                let start = chrono::Duration::from_std(elapsed)
+                    .ok()
+                    .and_then(|elapsed| local.checked_sub_signed(elapsed))
+                    .unwrap_or(local);
+                self.count_days(watts, start, local);
+            }
+        }
+        self.last_sample = Some(now);
+
+        if now.duration_since(self.last_saved) >= SAVE_INTERVAL {
+            self.last_saved = now;
+            if !self.owner && acquire(&self.lock) {
+                info!("Taking over the energy totals in {}", self.file.display());
+                self.owner = true;
+            }
+            if self.owner {
+                self.save();
+            } else {
+                self.totals = load(&self.file);
+            }
+        }
+    }
+
+    /// Splits the interval at each midnight, so every day gets its own part.
+    fn count_days(&mut self, watts: f64, mut start: DateTime<Local>, end: DateTime<Local>) {
+        while start < end {
+            let midnight = start
+                .date()
+                .succ()
+                .and_hms_opt(0, 0, 0)
+                .filter(|&midnight| midnight < end)
+                .unwrap_or(end);
+            let seconds = (midnight - start).num_milliseconds() as f64 / 1000.;
+            *self.totals.days.entry(day(start)).or_default() += watts * seconds / 3600.;
+            start = midnight;
+        }
+    }
+
+    pub fn reading(&self, cost_per_kwh: Option<f64>) -> EnergyReading {
+        let today_wh = self.totals.days.get(&day(Local::now())).copied();
+        EnergyReading {
+            session_wh: self.session_wh,
+            today_wh,
+            today_cost: today_wh
+                .zip(cost_per_kwh)
+                .map(|(wh, cost)| wh / 1000. * cost),
+        }
+    }
+
+    fn save(&mut self) {
+        while self.totals.days.len() > KEEP_DAYS {
+            let oldest = self.totals.days.keys().next().cloned();
+            if let Some(oldest) = oldest {
+                self.totals.days.remove(&oldest);
+            }
+        }
+        if let Err(e) = write(&self.file, &self.totals) {
+            warn!(
+                "Cannot save energy totals to {}: {}",
+                self.file.display(),
+                e
+            );
+        }
+    }
+}
+
+impl Drop for EnergyMeter {
+    fn drop(&mut self) {
+        if self.owner {
+            self.save();
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
+}
+
+/// `file` with `suffix` added to its name.
+fn beside(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn day(at: DateTime<Local>) -> String {
+    at.format("%Y-%m-%d").to_string()
+}
+
+/// Takes the lock unless a running process holds it.
+fn acquire(lock: &Path) -> bool {
+    if let Some(dir) = lock.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(lock) {
+            Ok(mut file) => return write!(file, "{}", process::id()).is_ok(),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let holder = fs::read_to_string(lock).unwrap_or_default();
+                let alive = holder
+                    .trim()
+                    .parse::<u32>()
+                    .is_ok_and(|pid| Path::new("/proc").join(pid.to_string()).exists());
+                if alive {
+                    return false;
+                }
+                // Left behind by a dashboard that didn't shut down cleanly.
+                let _ = fs::remove_file(lock);
+            }
+            Err(e) => {
+                warn!("Cannot lock {}: {}", lock.display(), e);
+                return false;
+            }
+        }
+    }
+    false
+}
+
+fn load(file: &Path) -> Totals {
+    match fs::read_to_string(file) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            warn!(
+                "Ignoring unreadable energy totals in {}: {}",
+                file.display(),
+                e
+            );
+            Totals::default()
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Totals::default(),
+        Err(e) => {
+            warn!("Cannot read energy totals from {}: {}", file.display(), e);
+            Totals::default()
+        }
+    }
+}
+
+/// Replaces `file` in one step, so a crash can't leave half of it behind.
+fn write(file: &Path, totals: &Totals) -> io::Result<()> {
+    let partial = beside(file, ".partial");
+    fs::write(&partial, serde_json::to_string_pretty(totals)?)?;
+    fs::rename(&partial, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::env;
+
+    /// A meter keeping its totals in a fresh directory.
+    fn meter(name: &str) -> EnergyMeter {
+        let dir = env::temp_dir().join(format!("sys-dashboard-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let settings = EnergySettings {
+            file: dir.join("energy.json"),
+            ..EnergySettings::default()
+        };
+        EnergyMeter::start(&settings).unwrap()
+    }
+
+    fn assert_wh(actual: Option<f64>, expected: f64) {
+        assert!(
+            actual.is_some_and(|wh| (wh - expected).abs() < 1e-9),
+            "{:?} Wh, expected {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn counts_power_over_time() {
+        let mut meter = meter("energy-counts");
+        let (now, local) = (Instant::now(), Local.ymd(2026, 3, 9).and_hms(12, 0, 0));
+        meter.add_at(Some(100.), now, local);
+        assert_eq!(meter.session_wh, None);
+
+        meter.add_at(
+            Some(100.),
+            now + Duration::from_secs(36),
+            local + chrono::Duration::seconds(36),
+        );
+        assert_wh(meter.session_wh, 1.);
+        assert_wh(meter.totals.days.get("2026-03-09").copied(), 1.);
+    }
+
+    #[test]
+    fn skips_intervals_without_a_reading() {
+        let mut meter = meter("energy-skips");
+        let (now, local) = (Instant::now(), Local.ymd(2026, 3, 9).and_hms(12, 0, 0));
+        meter.add_at(Some(100.), now, local);
+        meter.add_at(
+            None,
+            now + Duration::from_secs(36),
+            local + chrono::Duration::seconds(36),
+        );
+        assert_eq!(meter.session_wh, None);
+
+        meter.add_at(
+            Some(100.),
+            now + Duration::from_secs(72),
+            local + chrono::Duration::seconds(72),
+        );
+        assert_wh(meter.session_wh, 1.);
+    }
+
+    #[test]
+    fn splits_an_interval_at_midnight() {
+        let mut meter = meter("energy-midnight");
+        let now = Instant::now();
+        meter.add_at(Some(120.), now, Local.ymd(2026, 3, 8).and_hms(23, 59, 0));
+        meter.add_at(
+            Some(120.),
+            now + Duration::from_secs(90),
+            Local.ymd(2026, 3, 9).and_hms(0, 0, 30),
+        );
+        assert_wh(meter.session_wh, 3.);
+        assert_wh(meter.totals.days.get("2026-03-08").copied(), 2.);
+        assert_wh(meter.totals.days.get("2026-03-09").copied(), 1.);
+    }
+
+    #[test]
+    fn a_second_meter_only_counts_its_session() {
+        let keeper = meter("energy-shared");
+        let mut reader = EnergyMeter::start(&EnergySettings {
+            file: keeper.file.clone(),
+            ..EnergySettings::default()
+        })
+        .unwrap();
+        assert!(keeper.owner);
+        assert!(!reader.owner);
+
+        let (now, local) = (Instant::now(), Local.ymd(2026, 3, 9).and_hms(12, 0, 0));
+        reader.add_at(Some(100.), now, local);
+        reader.add_at(
+            Some(100.),
+            now + Duration::from_secs(36),
+            local + chrono::Duration::seconds(36),
+        );
+        assert_wh(reader.session_wh, 1.);
+        assert!(reader.totals.days.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where a reading comes from, so failures can be tracked and shown per
+/// source rather than per tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Clock,
+    User,
+    Host,
+    Os,
+    CpuName,
+    CpuUsage,
+    CpuTemp,
+    CpuFreq,
+    /// RAPL package energy, missing on most VMs and unreadable without
+    /// root on recent kernels, so it isn't shown with the CPU panel's
+    /// problems.
+    CpuPower,
+    Memory,
+    Gpu,
+    /// The agent a `--connect`ed dashboard reads from.
+    Connection,
+}
+
+impl Source {
+    pub const HEADER: &'static [Source] = &[
+        Source::Connection,
+        Source::Clock,
+        Source::User,
+        Source::Host,
+        Source::Os,
+    ];
+    pub const CPU: &'static [Source] = &[
+        Source::CpuName,
+        Source::CpuUsage,
+        Source::CpuTemp,
+        Source::CpuFreq,
+        Source::Memory,
+    ];
+    pub const GPU: &'static [Source] = &[Source::Gpu];
+
+    /// Stable snake_case name for machine-readable output.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Source::Clock => "clock",
+            Source::User => "user",
+            Source::Host => "host",
+            Source::Os => "os",
+            Source::CpuName => "cpu_name",
+            Source::CpuUsage => "cpu_usage",
+            Source::CpuTemp => "cpu_temp",
+            Source::CpuFreq => "cpu_freq",
+            Source::CpuPower => "cpu_power",
+            Source::Memory => "memory",
+            Source::Gpu => "gpu",
+            Source::Connection => "connection",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Source::Clock => "clock",
+            Source::User => "user",
+            Source::Host => "hostname",
+            Source::Os => "OS release",
+            Source::CpuName => "CPU name",
+            Source::CpuUsage => "CPU usage",
+            Source::CpuTemp => "CPU temperature",
+            Source::CpuFreq => "CPU frequency",
+            Source::CpuPower => "CPU power",
+            Source::Memory => "memory",
+            Source::Gpu => "GPU",
+            Source::Connection => "agent",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CollectorError {
+    /// A file under /proc, /sys or /etc could not be read.
+    Unreadable(PathBuf, String),
+    /// A command could not be started or exited unsuccessfully.
+    CommandFailed(String, String),
+    /// The data was there but not in the expected shape.
+    Malformed(String),
+    /// A remote agent could not be reached or spoke an unknown protocol.
+    Unreachable(String, String),
+}
+
+impl CollectorError {
+    pub fn unreadable(path: &Path, e: io::Error) -> Self {
+        CollectorError::Unreadable(path.to_path_buf(), e.to_string())
+    }
+
+    pub fn malformed<S: Into<String>>(what: S) -> Self {
+        CollectorError::Malformed(what.into())
+    }
+}
+
+impl fmt::Display for CollectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CollectorError::Unreadable(path, reason) => {
+                write!(f, "cannot read {}: {}", path.display(), reason)
+            }
+            CollectorError::CommandFailed(command, reason) => write!(f, "{}: {}", command, reason),
+            CollectorError::Malformed(what) => write!(f, "{}", what),
+            CollectorError::Unreachable(address, reason) => {
+                write!(f, "cannot reach {}: {}", address, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CollectorError {}
+
+impl From<csv::Error> for CollectorError {
+    fn from(e: csv::Error) -> Self {
+        CollectorError::Malformed(e.to_string())
+    }
+}
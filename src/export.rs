@@ -0,0 +1,195 @@
+use crate::history::{History, Selection};
+use crate::metric;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use gtk::prelude::*;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// Writes part of a recorded session as CSV, to `output` or stdout.
+pub fn run(session: &Path, output: Option<&Path>, selection: &Selection) {
+    let history = History::from_session(session).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+
+    let written = match output {
+        Some(path) => File::create(path)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+            .and_then(|file| history.write_csv(file, selection)),
+        None => history.write_csv(io::stdout(), selection),
+    };
+    if let Err(e) = written {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+/// How the range's times are typed, with or without seconds.
+const TIME_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"];
+
+/// Asks where to save, which metrics to include, over which range and how
+/// to resample.
+pub fn ask(parent: &gtk::ApplicationWindow) -> Option<(PathBuf, Selection)> {
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some("Export history"),
+        Some(parent),
+        gtk::FileChooserAction::Save,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Export", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_do_overwrite_confirmation(true);
+    dialog.set_current_name("sys-dashboard.csv");
+
+    let options = gtk::BoxBuilder::new()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    let columns = gtk::FlowBoxBuilder::new()
+        .selection_mode(gtk::SelectionMode::None)
+        .max_children_per_line(4)
+        .build();
+    let checks = metric::names()
+        .into_iter()
+        .map(|name| {
+            let check = gtk::CheckButton::with_label(name);
+            check.set_active(true);
+            columns.add(&check);
+            (name, check)
+        })
+        .collect::<Vec<_>>();
+    let resample = gtk::SpinButton::with_range(0., 3600., 1.);
+    let resample_row = gtk::BoxBuilder::new()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    resample_row.pack_start(
+        &gtk::Label::new(Some("Average over (s, 0 for every sample)")),
+        false,
+        false,
+        0,
+    );
+    resample_row.pack_start(&resample, false, false, 0);
+
+    let range = gtk::ComboBoxText::new();
+    range.append(Some("all"), "Everything");
+    range.append(Some("last"), "The last");
+    range.append(Some("between"), "Between");
+    range.set_active_id(Some("all"));
+    let minutes = gtk::SpinButton::with_range(1., 10080., 1.);
+    minutes.set_value(10.);
+    let from = gtk::Entry::new();
+    let to = gtk::Entry::new();
+    for entry in &[&from, &to] {
+        entry.set_placeholder_text(Some("2026-03-09 14:30"));
+    }
+    let range_row = gtk::BoxBuilder::new()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(6)
+        .build();
+    range_row.pack_start(&gtk::Label::new(Some("Range")), false, false, 0);
+    range_row.pack_start(&range, false, false, 0);
+    range_row.pack_start(&minutes, false, false, 0);
+    range_row.pack_start(&gtk::Label::new(Some("minutes")), false, false, 0);
+    range_row.pack_start(&from, false, false, 0);
+    range_row.pack_start(&gtk::Label::new(Some("to")), false, false, 0);
+    range_row.pack_start(&to, false, false, 0);
+    let choose = {
+        let (minutes, from, to) = (minutes.clone(), from.clone(), to.clone());
+        move |range: &gtk::ComboBoxText| {
+            let id = range.get_active_id();
+            minutes.set_sensitive(id.as_deref() == Some("last"));
+            from.set_sensitive(id.as_deref() == Some("between"));
+            to.set_sensitive(id.as_deref() == Some("between"));
+        }
+    };
+    choose(&range);
+    range.connect_changed(choose);
+
+    options.pack_start(&columns, false, false, 0);
+    options.pack_start(&range_row, false, false, 0);
+    options.pack_start(&resample_row, false, false, 0);
+    options.show_all();
+    dialog.set_extra_widget(&options);
+
+    let response = dialog.run();
+    let path = dialog.get_filename();
+    let range = match range.get_active_id().as_deref() {
+        Some("last") => Ok((None, None, Some(minutes.get_value_as_int() as u64 * 60_000))),
+        Some("between") => between(&from.get_text(), &to.get_text()),
+        _ => Ok((None, None, None)),
+    };
+    let selection = range.map(|(since_ms, until_ms, last_ms)| Selection {
+        columns: checks
+            .iter()
+            .filter(|(_, check)| check.get_active())
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        since_ms,
+        until_ms,
+        last_ms,
+        resample_ms: Some(resample.get_value_as_int() as u64 * 1000).filter(|&ms| ms > 0),
+    });
+    dialog.close();
+
+    match (response, path, selection) {
+        (gtk::ResponseType::Accept, Some(_), Err(e)) => {
+            show_error(parent, &e);
+            None
+        }
+        (gtk::ResponseType::Accept, Some(_), Ok(selection)) if selection.columns.is_empty() => {
+            show_error(parent, "Select at least one metric to export.");
+            None
+        }
+        (gtk::ResponseType::Accept, Some(path), Ok(selection)) => Some((path, selection)),
+        _ => None,
+    }
+}
+
+/// `since_ms`, `until_ms` and `last_ms` of a `Selection`.
+type Range = (Option<i64>, Option<i64>, Option<u64>);
+
+/// From one local time to another, either left empty to leave that end
+/// open.
+fn between(from: &str, to: &str) -> Result<Range, String> {
+    let (since_ms, until_ms) = (local_time_ms(from)?, local_time_ms(to)?);
+    if let (Some(since), Some(until)) = (since_ms, until_ms) {
+        if since > until {
+            return Err("The range ends before it starts.".to_string());
+        }
+    }
+    Ok((since_ms, until_ms, None))
+}
+
+fn local_time_ms(text: &str) -> Result<Option<i64>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .and_then(|time| Local.from_local_datetime(&time).earliest())
+        .map(|time| Some(time.timestamp_millis()))
+        .ok_or_else(|| format!("'{}' is not a time like 2026-03-09 14:30.", text))
+}
+
+pub fn write(path: &Path, history: &History, selection: &Selection) -> Result<usize, String> {
+    let file = File::create(path).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    history.write_csv(file, selection)
+}
+
+pub fn show_error(parent: &gtk::ApplicationWindow, message: &str) {
+    let dialog = gtk::MessageDialog::new(
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Close,
+        message,
+    );
+    dialog.run();
+    dialog.close();
+}
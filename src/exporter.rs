@@ -0,0 +1,417 @@
+use crate::config::Config;
+use crate::error::Source;
+use crate::system::{GPUInfo, SystemInfo};
+use crate::units::Bytes;
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+/// Length of a /proc/stat clock tick. USER_HZ is 100 on every architecture
+/// Linux exposes it on.
+const TICKS_PER_SECOND: f64 = 100.;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExporterSettings {
+    pub enabled: bool,
+    pub listen: SocketAddr,
+}
+
+impl Default for ExporterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: SocketAddr::from(([127, 0, 0, 1], 9101)),
+        }
+    }
+}
+
+/// Serves the latest reading on `/metrics` in the OpenMetrics text format,
+/// so Prometheus can scrape the dashboard like a node exporter.
+pub struct Exporter {
+    page: Arc<Mutex<String>>,
+}
+
+impl Exporter {
+    /// Binds the listener, or returns `None` when the exporter is disabled
+    /// or the address can't be bound. Neither stops the dashboard.
+    pub fn start(settings: &ExporterSettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        let listener = match TcpListener::bind(settings.listen) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Cannot serve metrics on {}: {}", settings.listen, e);
+                return None;
+            }
+        };
+        info!("Serving metrics on http://{}/metrics", settings.listen);
+
+        let page = Arc::new(Mutex::new("# EOF\n".to_string()));
+        let served = page.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = serve(stream, &served) {
+                            warn!("Metrics request failed: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Cannot accept metrics connection: {}", e),
+                }
+            }
+        });
+        Some(Self { page })
+    }
+
+    pub fn publish(&self, system_info: &SystemInfo, config: &Config) {
+        let page = render(system_info, config);
+        *self.page.lock().expect("Metrics page lock poisoned") = page;
+    }
+}
+
+fn serve(stream: TcpStream, page: &Mutex<String>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers, none of them change the response.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header != "\r\n" && header != "\n" {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => {
+            let body = page.lock().expect("Metrics page lock poisoned").clone();
+            response("200 OK", CONTENT_TYPE, &body)
+        }
+        (Some("GET"), Some(_)) => response("404 Not Found", "text/plain", "Try /metrics\n"),
+        _ => response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is supported\n",
+        ),
+    };
+    (&stream).write_all(response.as_bytes())
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// One metric family: its metadata followed by one sample per label set.
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    unit: &'static str,
+    help: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Family {
+    fn gauge(name: &'static str, unit: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            kind: "gauge",
+            unit,
+            help,
+            samples: vec![],
+        }
+    }
+
+    fn counter(name: &'static str, unit: &'static str, help: &'static str) -> Self {
+        Self {
+            kind: "counter",
+            ..Self::gauge(name, unit, help)
+        }
+    }
+
+    /// Text details as labels on a sample that is always 1.
+    fn info(name: &'static str, help: &'static str) -> Self {
+        Self {
+            kind: "info",
+            ..Self::gauge(name, "", help)
+        }
+    }
+
+    /// One sample per card, labelled with its index and name.
+    fn per_gpu(mut self, gpus: &[GPUInfo], value: fn(&GPUInfo) -> Option<f64>) -> Self {
+        for (index, gpu) in gpus.iter().enumerate() {
+            self = self.sample(
+                vec![("gpu", index.to_string()), ("name", gpu.name.clone())],
+                value(gpu),
+            );
+        }
+        self
+    }
+
+    fn sample(mut self, labels: Vec<(&'static str, String)>, value: Option<f64>) -> Self {
+        if let Some(value) = value {
+            self.samples.push((labels, value));
+        }
+        self
+    }
+
+    fn write(&self, out: &mut String) {
+        if self.samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        if !self.unit.is_empty() {
+            let _ = writeln!(out, "# UNIT {} {}", self.name, self.unit);
+        }
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let suffix = match self.kind {
+            "counter" => "_total",
+            "info" => "_info",
+            _ => "",
+        };
+        for (labels, value) in &self.samples {
+            let labels = labels
+                .iter()
+                .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            if labels.is_empty() {
+                let _ = writeln!(out, "{}{} {}", self.name, suffix, value);
+            } else {
+                let _ = writeln!(out, "{}{}{{{}}} {}", self.name, suffix, labels, value);
+            }
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Everything is exposed in base units (seconds, bytes, hertz, ratios)
+/// as Prometheus expects, not the units the dashboard displays.
+fn render(system_info: &SystemInfo, config: &Config) -> String {
+    let sensors = &config.sensors;
+    let gpus = &system_info.gpus;
+    let cpu_healthy = !system_info.errors.contains_key(&Source::CpuUsage);
+    let memory_healthy = !system_info.errors.contains_key(&Source::Memory);
+
+    let mut cpu_seconds = Family::counter(
+        "sysdash_cpu_seconds",
+        "seconds",
+        "Time each core spent in each mode.",
+    );
+    for (core, times) in system_info.core_times().iter().enumerate() {
+        for (mode, ticks) in times.modes().iter() {
+            cpu_seconds = cpu_seconds.sample(
+                vec![("cpu", core.to_string()), ("mode", mode.to_string())],
+                Some(*ticks as f64 / TICKS_PER_SECOND),
+            );
+        }
+    }
+
+    let mut cpu_usage = Family::gauge(
+        "sysdash_cpu_usage_ratio",
+        "ratio",
+        "Busy share of the last refresh interval, per core and overall.",
+    )
+    .sample(
+        vec![("cpu", "all".to_string())],
+        Some(system_info.cpu_usage as f64 / 100.).filter(|_| cpu_healthy),
+    );
+    for (core, usage) in system_info.core_usage.iter().enumerate() {
+        cpu_usage = cpu_usage.sample(
+            vec![("cpu", core.to_string())],
+            Some(*usage as f64 / 100.).filter(|_| cpu_healthy),
+        );
+    }
+
+    let mut cpu_frequency = Family::gauge(
+        "sysdash_cpu_frequency_hertz",
+        "hertz",
+        "Current frequency of each core.",
+    );
+    for (core, mhz) in system_info.cpu_freq.iter().enumerate() {
+        cpu_frequency = cpu_frequency.sample(
+            vec![("cpu", core.to_string())],
+            Some((*mhz as f64 * 1e6).round())
+                .filter(|_| !system_info.errors.contains_key(&Source::CpuFreq)),
+        );
+    }
+
+    let mut collector_up = Family::gauge(
+        "sysdash_collector_up",
+        "",
+        "Whether the last reading from each source succeeded.",
+    );
+    for source in Source::HEADER.iter().chain(Source::CPU).chain(Source::GPU) {
+        collector_up = collector_up.sample(
+            vec![("source", source.id().to_string())],
+            Some(if system_info.errors.contains_key(source) {
+                0.
+            } else {
+                1.
+            }),
+        );
+    }
+
+    let families = vec![
+        Family::info("sysdash", "Static details about the host.").sample(
+            vec![
+                ("host", system_info.host.clone()),
+                ("os", system_info.os.clone()),
+                ("cpu", system_info.cpu_name.clone()),
+            ],
+            Some(1.),
+        ),
+        collector_up,
+        cpu_seconds,
+        cpu_usage,
+        cpu_frequency,
+        Family::gauge(
+            "sysdash_cpu_temperature_celsius",
+            "celsius",
+            "Temperature of the configured thermal zone.",
+        )
+        .sample(
+            vec![("zone", sensors.cpu_thermal_zone.to_string())],
+            Some(system_info.cpu_temp as f64)
+                .filter(|_| !system_info.errors.contains_key(&Source::CpuTemp)),
+        ),
+        Family::gauge(
+            "sysdash_cpu_power_draw_watts",
+            "watts",
+            "CPU package power draw, from RAPL.",
+        )
+        .sample(vec![], system_info.cpu_power.map(f64::from)),
+        Family::gauge(
+            "sysdash_memory_total_bytes",
+            "bytes",
+            "Usable physical memory.",
+        )
+        .sample(
+            vec![],
+            Some(Bytes::from_kib(system_info.memory_info.total as f64).0)
+                .filter(|_| memory_healthy),
+        ),
+        Family::gauge(
+            "sysdash_memory_available_bytes",
+            "bytes",
+            "Memory available to new processes without swapping.",
+        )
+        .sample(
+            vec![],
+            Some(Bytes::from_kib(system_info.memory_info.available as f64).0)
+                .filter(|_| memory_healthy),
+        ),
+        Family::gauge(
+            "sysdash_gpu_temperature_celsius",
+            "celsius",
+            "GPU core temperature.",
+        )
+        .per_gpu(gpus, |gpu| gpu.temperature.map(f64::from)),
+        Family::gauge(
+            "sysdash_gpu_utilization_ratio",
+            "ratio",
+            "Share of time the GPU was busy.",
+        )
+        .per_gpu(gpus, |gpu| gpu.utilization.map(|u| u as f64 / 100.)),
+        Family::gauge(
+            "sysdash_gpu_memory_used_bytes",
+            "bytes",
+            "GPU memory in use.",
+        )
+        .per_gpu(gpus, |gpu| {
+            gpu.used_memory.map(|mib| Bytes::from_mib(mib as f64).0)
+        }),
+        Family::gauge(
+            "sysdash_gpu_memory_total_bytes",
+            "bytes",
+            "GPU memory installed.",
+        )
+        .per_gpu(gpus, |gpu| {
+            gpu.total_memory.map(|mib| Bytes::from_mib(mib as f64).0)
+        }),
+        Family::gauge(
+            "sysdash_gpu_power_draw_watts",
+            "watts",
+            "GPU board power draw.",
+        )
+        .per_gpu(gpus, |gpu| gpu.power_draw.map(f64::from)),
+        Family::gauge(
+            "sysdash_gpu_power_limit_watts",
+            "watts",
+            "GPU board power limit.",
+        )
+        .per_gpu(gpus, |gpu| gpu.power_limit.map(f64::from)),
+        Family::gauge(
+            "sysdash_energy_today_joules",
+            "joules",
+            "CPU and GPU energy used since local midnight.",
+        )
+        .sample(vec![], system_info.energy.today_wh.map(|wh| wh * 3600.)),
+    ];
+
+    let mut out = String::new();
+    for family in &families {
+        family.write(&mut out);
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(name: &str, temperature: u8, power_draw: Option<f32>) -> GPUInfo {
+        GPUInfo {
+            name: name.to_string(),
+            temperature: Some(temperature),
+            power_draw,
+            ..GPUInfo::default()
+        }
+    }
+
+    #[test]
+    fn every_gpu_gets_a_sample() {
+        let mut system_info = SystemInfo::default();
+        system_info.gpus = vec![
+            card("NVIDIA A100", 61, Some(397.5)),
+            card("NVIDIA A100", 33, None),
+        ];
+        let page = render(&system_info, &Config::default());
+        assert!(page.contains(
+            "sysdash_gpu_temperature_celsius{gpu=\"0\",name=\"NVIDIA A100\"} 61\n\
+             sysdash_gpu_temperature_celsius{gpu=\"1\",name=\"NVIDIA A100\"} 33\n"
+        ));
+        assert!(
+            page.contains("sysdash_gpu_power_draw_watts{gpu=\"0\",name=\"NVIDIA A100\"} 397.5\n")
+        );
+        assert!(!page.contains("sysdash_gpu_power_draw_watts{gpu=\"1\""));
+    }
+
+    #[test]
+    fn host_details_are_an_info_metric() {
+        let mut system_info = SystemInfo::default();
+        system_info.os = "Ubuntu 20.04.2 LTS".to_string();
+        let page = render(&system_info, &Config::default());
+        assert!(page.contains("# TYPE sysdash info\n"));
+        assert!(page.contains("sysdash_info{host=\"\",os=\"Ubuntu 20.04.2 LTS\",cpu=\"\"} 1\n"));
+    }
+}
@@ -1,3 +1,4 @@
+use gtk::prelude::*;
 use gtk::Align;
 use std::fmt::Display;
 
@@ -11,6 +12,8 @@ pub fn trim_newline(s: &mut String) {
     }
 }
 
+pub const NOT_AVAILABLE: &str = "n/a";
+
 pub trait Percentify {
     fn as_percentage(&self) -> String;
 }
@@ -21,16 +24,6 @@ impl<T: Display> Percentify for T {
     }
 }
 
-pub trait Celcify {
-    fn as_celcius(&self) -> String;
-}
-
-impl<T: Display> Celcify for T {
-    fn as_celcius(&self) -> String {
-        format!("{:>3}C", self)
-    }
-}
-
 pub fn create_label(label: &str, align: Align) -> gtk::Label {
     gtk::LabelBuilder::new()
         .name(label)
@@ -40,15 +33,17 @@ pub fn create_label(label: &str, align: Align) -> gtk::Label {
 }
 
 pub trait Name {
-    fn as_long_field_name(&self, field: &str) -> String;
     fn as_field_name(&self, field: &str) -> String;
 }
 
 impl<T: Display> Name for T {
-    fn as_long_field_name(&self, field: &str) -> String {
-        format!("{} : {:<40}", field, self)
-    }
     fn as_field_name(&self, field: &str) -> String {
         format!("{} : {}", field, self)
     }
 }
+
+/// Shows `problems` one per line on `status`, hiding it while there are none.
+pub fn show_problems(status: &gtk::Label, problems: &[String]) {
+    status.set_text(&problems.join("\n"));
+    status.set_visible(!problems.is_empty());
+}
@@ -1,5 +1,8 @@
-use crate::fmt::{create_label, Celcify, Name, Percentify};
+use crate::config::Config;
+use crate::error::Source;
+use crate::fmt::{create_label, show_problems, Percentify};
 use crate::system::SystemInfo;
+use crate::units::{self, Temperature, Units};
 use cairo::{Context, Format, ImageSurface};
 use gdk::prelude::IsA;
 use gtk::{Align, BoxExt, GridExt, LabelExt, Orientation, StyleContextExt, Widget, WidgetExt};
@@ -15,6 +18,7 @@ pub struct GPUView {
     power_limit: gtk::Label,
     memory_used: gtk::Label,
     memory_total: gtk::Label,
+    status: gtk::Label,
 }
 
 impl GPUView {
@@ -29,6 +33,8 @@ impl GPUView {
         let power_limit = create_label("power_limit", Align::Start);
         let memory_used = create_label("memory_used", Align::Start);
         let memory_total = create_label("memory_total", Align::Start);
+        let status = create_label("status", Align::Start);
+        status.set_no_show_all(true);
 
         let arc_box = gtk::BoxBuilder::new()
             .orientation(Orientation::Vertical)
@@ -59,8 +65,9 @@ impl GPUView {
         container.attach(&memory_used, 2, 3, 1, 1);
         container.attach(&memory_total, 2, 4, 1, 1);
         container.attach(&arc_box, 0, 1, 1, 5);
-        gpu_usage.set_text(&*100u8.as_percentage());
-        gpu_temp.set_text(&*100u8.as_celcius());
+        container.attach(&status, 0, 6, 3, 1);
+        gpu_usage.set_text(&100u8.as_percentage());
+        gpu_temp.set_text(&units::show(Temperature(100.), &Units::default()));
 
         Self {
             container,
@@ -72,25 +79,33 @@ impl GPUView {
             power_limit,
             memory_used,
             memory_total,
+            status,
         }
     }
 
-    pub fn update(&self, system_info: &SystemInfo) {
+    pub fn update(&self, system_info: &SystemInfo, config: &Config) {
         let gpu_info = &system_info.gpu_info;
-        self.gpu_temp.set_text(&gpu_info.temperature.as_celcius());
+        let labels = &config.labels;
+        let units = &config.units;
+        self.gpu_temp
+            .set_text(&labels.gpu_temperature.render(system_info, units));
         self.gpu_usage
-            .set_text(&gpu_info.utilization.as_percentage());
+            .set_text(&labels.gpu_usage.render(system_info, units));
         self.gpu_name
-            .set_text(&gpu_info.name.as_long_field_name("GPU"));
+            .set_text(&labels.gpu_name.render(system_info, units));
         self.power_draw
-            .set_text(&(gpu_info.power_draw as u32).as_field_name("Power Draw (W)"));
+            .set_text(&labels.power_draw.render(system_info, units));
         self.power_limit
-            .set_text(&(gpu_info.power_limit as u32).as_field_name("Power Limit (W)"));
+            .set_text(&labels.power_limit.render(system_info, units));
         self.memory_used
-            .set_text(&gpu_info.used_memory.as_field_name("Memory Used (MiB)"));
+            .set_text(&labels.gpu_memory_used.render(system_info, units));
         self.memory_total
-            .set_text(&gpu_info.total_memory.as_field_name("Memory Total (MiB)"));
-        update_usage(&self.gpu_usage_arc, gpu_info.utilization);
+            .set_text(&labels.gpu_memory_total.render(system_info, units));
+        update_usage(
+            &self.gpu_usage_arc,
+            gpu_info.utilization.unwrap_or_default(),
+        );
+        show_problems(&self.status, &system_info.problems(Source::GPU));
         self.container.queue_draw()
     }
 
@@ -1,4 +1,6 @@
-use crate::fmt::get_session_name;
+use crate::config::Config;
+use crate::error::Source;
+use crate::fmt::{get_session_name, show_problems};
 use crate::system::SystemInfo;
 use gtk::prelude::*;
 use gtk::{Align, Orientation, Widget};
@@ -8,6 +10,9 @@ pub struct HeaderView {
     session_info: gtk::Label,
     os_info: gtk::Label,
     session_time: gtk::Label,
+    energy: gtk::Label,
+    energy_cost: gtk::Label,
+    status: gtk::Label,
 }
 
 impl HeaderView {
@@ -18,10 +23,18 @@ impl HeaderView {
         let session_info = create_label("session_info");
         let os_info = create_label("os_info");
         let session_time = create_label("session_time");
+        let energy = create_label("energy");
+        let energy_cost = create_label("energy_cost");
+        energy_cost.set_no_show_all(true);
+        let status = create_label("status");
+        status.set_no_show_all(true);
 
         container.pack_start(&session_info, false, false, 0);
         container.pack_start(&os_info, false, false, 0);
         container.pack_start(&session_time, false, false, 0);
+        container.pack_start(&energy, false, false, 0);
+        container.pack_start(&energy_cost, false, false, 0);
+        container.pack_start(&status, false, false, 0);
 
         // Dummy Data
         session_info.set_label("User@Host");
@@ -33,14 +46,25 @@ impl HeaderView {
             session_info,
             os_info,
             session_time,
+            energy,
+            energy_cost,
+            status,
         }
     }
 
-    pub fn update(&self, system_info: &SystemInfo) {
+    pub fn update(&self, system_info: &SystemInfo, config: &Config) {
         self.session_info
             .set_label(&get_session_name(&system_info.user, &system_info.host));
-        self.session_time.set_label(&*system_info.datetime);
-        self.os_info.set_label(&*system_info.os);
+        self.session_time.set_label(&system_info.datetime);
+        self.os_info.set_label(&system_info.os);
+        let labels = &config.labels;
+        self.energy
+            .set_label(&labels.energy.render(system_info, &config.units));
+        self.energy_cost
+            .set_label(&labels.energy_cost.render(system_info, &config.units));
+        self.energy_cost
+            .set_visible(config.energy.cost_per_kwh.is_some());
+        show_problems(&self.status, &system_info.problems(Source::HEADER));
     }
 
     pub(super) fn widget(&self) -> &impl IsA<Widget> {
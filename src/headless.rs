@@ -0,0 +1,135 @@
+use crate::alert::Alerts;
+use crate::config::{Config, Overrides};
+use crate::exporter::Exporter;
+use crate::mqtt::Mqtt;
+use crate::session::Recorder;
+use crate::sink::Sinks;
+use crate::system::SystemInfo;
+use log::{error, info};
+use std::fs::metadata;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Set by SIGINT and SIGTERM. The loop checks it between refreshes and
+/// returns, so everything it owns shuts down through `Drop`.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// The longest the loop sleeps before checking `STOPPING`.
+const STOP_CHECK: Duration = Duration::from_millis(100);
+
+/// Runs the collectors and alert hooks without touching GTK until SIGINT or
+/// SIGTERM.
+pub fn run(config_path: &Path, overrides: &Overrides, mut recorder: Option<Recorder>) {
+    run_with(config_path, overrides, |system_info, _| {
+        if let Some(recorder) = &mut recorder {
+            recorder.record(system_info);
+        }
+    });
+}
+
+/// Like `run`, also handing every fresh reading to `on_update`.
+pub fn run_with<F>(config_path: &Path, overrides: &Overrides, mut on_update: F)
+where
+    F: FnMut(&SystemInfo, &Config),
+{
+    let mut config = load(config_path, overrides);
+    stop_on_signals();
+    let mut modified = modified_time(config_path);
+    let mut system_info = SystemInfo::new(&config);
+    system_info.start_energy_meter(&config.energy);
+    let mut alerts = Alerts::new(config.alert.clone(), config.hooks);
+    let exporter = Exporter::start(&config.exporter);
+    let mut sinks = Sinks::new(config.sink.clone());
+    let mut mqtt = Mqtt::new(&config.mqtt);
+
+    loop {
+        system_info.update();
+        alerts.evaluate(&system_info);
+        if let Some(exporter) = &exporter {
+            exporter.publish(&system_info, &config);
+        }
+        sinks.publish(&system_info);
+        if let Some(mqtt) = &mut mqtt {
+            mqtt.publish(&system_info);
+        }
+        on_update(&system_info, &config);
+        if !sleep(Duration::from_millis(config.refresh.interval_ms)) {
+            info!("Stopping");
+            return;
+        }
+
+        if let Some(reloaded) = reload(config_path, overrides, &mut modified) {
+            system_info.configure(&reloaded);
+            alerts.reconfigure(reloaded.alert.clone(), reloaded.hooks);
+            sinks.reconfigure(reloaded.sink.clone());
+            mqtt = Mqtt::new(&reloaded.mqtt);
+            config = reloaded;
+        }
+    }
+}
+
+pub(crate) fn load(config_path: &Path, overrides: &Overrides) -> Config {
+    Config::load(config_path, overrides).unwrap_or_else(|e| {
+        error!("Using default configuration: {}", e);
+        Config::default()
+    })
+}
+
+/// The config file again if it changed since `modified`. Without a main
+/// loop there is no file monitor, so this polls.
+pub(crate) fn reload(
+    config_path: &Path,
+    overrides: &Overrides,
+    modified: &mut Option<SystemTime>,
+) -> Option<Config> {
+    let latest = modified_time(config_path);
+    if latest == *modified {
+        return None;
+    }
+    *modified = latest;
+    match Config::load(config_path, overrides) {
+        Ok(reloaded) => {
+            info!("Reloaded {}", config_path.display());
+            Some(reloaded)
+        }
+        Err(e) => {
+            error!("Keeping previous configuration: {}", e);
+            None
+        }
+    }
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    STOPPING.store(true, Ordering::SeqCst);
+}
+
+pub(crate) fn stop_on_signals() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // Only stores to an atomic, which is safe in a signal handler.
+        unsafe {
+            libc::signal(
+                signal,
+                on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+/// Sleeps for `duration`, false if woken early to stop.
+pub(crate) fn sleep(duration: Duration) -> bool {
+    let until = Instant::now() + duration;
+    while !STOPPING.load(Ordering::SeqCst) {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(STOP_CHECK));
+    }
+    false
+}
+
+pub(crate) fn modified_time(path: &Path) -> Option<SystemTime> {
+    metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,310 @@
+use crate::metric;
+use crate::session::Session;
+use crate::system::SystemInfo;
+use chrono::{Local, TimeZone};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistorySettings {
+    /// How far back the dashboard remembers readings for export.
+    pub retention_secs: u64,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            retention_secs: 3600,
+        }
+    }
+}
+
+struct Sample {
+    unix_ms: i64,
+    /// One entry per `metric::names()`.
+    values: Vec<Option<f64>>,
+}
+
+/// Every metric over a window of time, oldest first.
+pub struct History {
+    samples: VecDeque<Sample>,
+    retention_ms: Option<i64>,
+}
+
+/// Column names, then each row's time and values.
+pub type Rows = (Vec<&'static str>, Vec<(i64, Vec<Option<f64>>)>);
+
+/// Which part of the history to export.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    /// Metric names, all of them when empty.
+    pub columns: Vec<String>,
+    pub since_ms: Option<i64>,
+    pub until_ms: Option<i64>,
+    /// Only this long up to the newest sample, instead of `since_ms`.
+    pub last_ms: Option<u64>,
+    /// Average into buckets this long instead of writing every sample.
+    pub resample_ms: Option<u64>,
+}
+
+impl History {
+    pub fn new(settings: &HistorySettings) -> Self {
+        let mut history = Self::unbounded();
+        history.configure(settings);
+        history
+    }
+
+    pub fn configure(&mut self, settings: &HistorySettings) {
+        self.retention_ms = Some(settings.retention_secs as i64 * 1000);
+        self.trim();
+    }
+
+    /// A history that keeps everything pushed to it, however old.
+    pub fn unbounded() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            retention_ms: None,
+        }
+    }
+
+    pub fn from_session(path: &Path) -> Result<Self, String> {
+        let mut history = Self::unbounded();
+        Session::open(path)?.for_each(|unix_ms, system_info| history.push(unix_ms, system_info));
+        Ok(history)
+    }
+
+    pub fn push(&mut self, unix_ms: i64, system_info: &SystemInfo) {
+        let values = metric::collect(system_info)
+            .into_iter()
+            .map(|m| m.value)
+            .collect();
+        self.samples.push_back(Sample { unix_ms, values });
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let (retention_ms, newest) = match (self.retention_ms, self.samples.back()) {
+            (Some(retention_ms), Some(newest)) => (retention_ms, newest.unix_ms),
+            _ => return,
+        };
+        while self
+            .samples
+            .front()
+            .is_some_and(|oldest| newest - oldest.unix_ms > retention_ms)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn newest_ms(&self) -> Option<i64> {
+        self.samples.back().map(|sample| sample.unix_ms)
+    }
+
+    /// The names of the selected columns and, for every selected sample,
+    /// its time and the value of each column.
+    pub fn rows(&self, selection: &Selection) -> Result<Rows, String> {
+        let names = metric::names();
+        let columns = if selection.columns.is_empty() {
+            (0..names.len()).collect::<Vec<_>>()
+        } else {
+            selection
+                .columns
+                .iter()
+                .map(|column| {
+                    names
+                        .iter()
+                        .position(|name| name == column)
+                        .ok_or_else(|| format!("unknown metric '{}'", column))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let since_ms = match (selection.last_ms, self.newest_ms()) {
+            (Some(last_ms), Some(newest)) => Some(
+                i64::try_from(last_ms)
+                    .ok()
+                    .and_then(|last_ms| newest.checked_sub(last_ms))
+                    .ok_or_else(|| format!("the last {}ms reach too far back", last_ms))?,
+            ),
+            _ => selection.since_ms,
+        };
+        let samples = self.samples.iter().filter(|sample| {
+            since_ms.is_none_or(|since| sample.unix_ms >= since)
+                && selection
+                    .until_ms
+                    .is_none_or(|until| sample.unix_ms <= until)
+        });
+        let rows = match selection.resample_ms {
+            Some(resample_ms) if resample_ms > 0 => resample(samples, resample_ms as i64),
+            _ => samples
+                .map(|sample| (sample.unix_ms, sample.values.clone()))
+                .collect(),
+        };
+        let rows = rows
+            .into_iter()
+            .map(|(unix_ms, values)| (unix_ms, columns.iter().map(|&i| values[i]).collect()))
+            .collect();
+        Ok((columns.iter().map(|&i| names[i]).collect(), rows))
+    }
+
+    /// Writes the selected columns as CSV, a `timestamp` and `unix_ms`
+    /// column first. Returns the number of rows written.
+    pub fn write_csv<W: Write>(&self, writer: W, selection: &Selection) -> Result<usize, String> {
+        let (columns, rows) = self.rows(selection)?;
+        let mut csv = csv::Writer::from_writer(writer);
+        let header = ["timestamp", "unix_ms"]
+            .iter()
+            .copied()
+            .chain(columns.iter().copied());
+        csv.write_record(header).map_err(|e| e.to_string())?;
+        for (unix_ms, values) in &rows {
+            let record = [
+                Local.timestamp_millis(*unix_ms).to_rfc3339(),
+                unix_ms.to_string(),
+            ]
+            .iter()
+            .cloned()
+            .chain(
+                values
+                    .iter()
+                    .map(|value| value.map(|v| v.to_string()).unwrap_or_default()),
+            )
+            .collect::<Vec<_>>();
+            csv.write_record(&record).map_err(|e| e.to_string())?;
+        }
+        csv.flush().map_err(|e| e.to_string())?;
+        Ok(rows.len())
+    }
+}
+
+/// Averages samples into buckets aligned to multiples of `bucket_ms`,
+/// stamped with the bucket's start. Readings a sample lacks are left out of
+/// the average rather than counted as zero.
+fn resample<'a, I>(samples: I, bucket_ms: i64) -> Vec<(i64, Vec<Option<f64>>)>
+where
+    I: Iterator<Item = &'a Sample>,
+{
+    let mut rows: Vec<(i64, Vec<(f64, u32)>)> = vec![];
+    for sample in samples {
+        let bucket = sample.unix_ms - sample.unix_ms.rem_euclid(bucket_ms);
+        if rows.last().is_none_or(|(start, _)| *start != bucket) {
+            rows.push((bucket, vec![(0., 0); sample.values.len()]));
+        }
+        let (_, sums) = rows.last_mut().expect("pushed above");
+        for (sum, value) in sums.iter_mut().zip(&sample.values) {
+            if let Some(value) = value {
+                sum.0 += value;
+                sum.1 += 1;
+            }
+        }
+    }
+    rows.into_iter()
+        .map(|(start, sums)| {
+            let values = sums
+                .into_iter()
+                .map(|(sum, count)| {
+                    if count == 0 {
+                        None
+                    } else {
+                        Some(sum / count as f64)
+                    }
+                })
+                .collect();
+            (start, values)
+        })
+        .collect()
+}
+
+/// `500ms`, `10s`, `5m` or `2h`.
+pub fn parse_duration_ms(value: &str) -> Result<u64, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("'{}' is not a duration like 10s or 5m", value))?;
+    let scale = match unit {
+        "ms" => 1,
+        "s" | "" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        _ => return Err(format!("unknown unit '{}' in '{}'", unit, value)),
+    };
+    number
+        .checked_mul(scale)
+        .ok_or_else(|| format!("'{}' is too long", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sample every second from `from_ms`.
+    fn history(from_ms: i64, samples: i64) -> History {
+        let mut history = History::unbounded();
+        for i in 0..samples {
+            history.push(from_ms + i * 1000, &SystemInfo::default());
+        }
+        history
+    }
+
+    fn times(history: &History, selection: &Selection) -> Vec<i64> {
+        let (_, rows) = history.rows(selection).unwrap();
+        rows.into_iter().map(|(unix_ms, _)| unix_ms).collect()
+    }
+
+    #[test]
+    fn last_counts_back_from_the_newest_sample() {
+        let history = history(1_000_000, 10);
+        let selection = Selection {
+            last_ms: Some(2000),
+            ..Selection::default()
+        };
+        assert_eq!(
+            times(&history, &selection),
+            [1_007_000, 1_008_000, 1_009_000]
+        );
+    }
+
+    #[test]
+    fn last_reaching_too_far_back_is_rejected() {
+        let history = history(1_000_000, 2);
+        for last_ms in [u64::MAX, i64::MAX as u64 + 1] {
+            let selection = Selection {
+                last_ms: Some(last_ms),
+                ..Selection::default()
+            };
+            assert!(history.rows(&selection).is_err(), "{}", last_ms);
+        }
+    }
+
+    #[test]
+    fn since_and_until_are_inclusive() {
+        let history = history(1_000_000, 10);
+        let selection = Selection {
+            since_ms: Some(1_002_000),
+            until_ms: Some(1_004_000),
+            ..Selection::default()
+        };
+        assert_eq!(
+            times(&history, &selection),
+            [1_002_000, 1_003_000, 1_004_000]
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration_ms("500ms"), Ok(500));
+        assert_eq!(parse_duration_ms("10"), Ok(10_000));
+        assert_eq!(parse_duration_ms("5m"), Ok(300_000));
+        assert_eq!(parse_duration_ms("2h"), Ok(7_200_000));
+        assert!(parse_duration_ms("5d").is_err());
+        assert!(parse_duration_ms("m").is_err());
+        assert!(parse_duration_ms("18446744073709551615h").is_err());
+    }
+}
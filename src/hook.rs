@@ -0,0 +1,277 @@
+use crate::alert::AlertEvent;
+use log::{info, warn};
+use serde::Deserialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HookSettings {
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_max_concurrent() -> usize {
+    4
+}
+
+impl Default for HookSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            max_concurrent: default_max_concurrent(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HookAction {
+    Command(String),
+    Fifo(PathBuf),
+}
+
+pub struct HookRunner {
+    settings: HookSettings,
+    running: Arc<AtomicUsize>,
+}
+
+impl HookRunner {
+    pub fn new(settings: HookSettings) -> Self {
+        Self {
+            settings,
+            running: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn run(&self, action: &HookAction, event: &AlertEvent) {
+        if self.running.fetch_add(1, Ordering::SeqCst) >= self.settings.max_concurrent {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            warn!(
+                "Skipping hook for alert '{}': {} hooks already running.",
+                event.name, self.settings.max_concurrent
+            );
+            return;
+        }
+
+        let action = action.clone();
+        let env = hook_env(event);
+        let timeout = Duration::from_secs(self.settings.timeout_secs);
+        let running = self.running.clone();
+        let name = event.name.clone();
+
+        thread::spawn(move || {
+            match action {
+                HookAction::Command(command) => run_command(&name, &command, &env, timeout),
+                HookAction::Fifo(path) => write_fifo(&name, &path, &env),
+            }
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+fn hook_env(event: &AlertEvent) -> Vec<(&'static str, String)> {
+    vec![
+        ("SYSDASH_ALERT_NAME", event.name.clone()),
+        ("SYSDASH_ALERT_METRIC", event.metric.clone()),
+        ("SYSDASH_ALERT_VALUE", event.value.to_string()),
+        ("SYSDASH_ALERT_STATE", event.state.to_string()),
+    ]
+}
+
+fn run_command(name: &str, command: &str, env: &[(&str, String)], timeout: Duration) {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        // Anything printed would land in a bar's status stream or over the
+        // TUI.
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to start hook for alert '{}': {}", name, e);
+            return;
+        }
+    };
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return,
+            Ok(Some(status)) => {
+                warn!("Hook for alert '{}' exited with {}", name, status);
+                return;
+            }
+            Ok(None) if started.elapsed() >= timeout => {
+                warn!(
+                    "Hook for alert '{}' timed out after {:?}, killing it.",
+                    name, timeout
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(e) => {
+                warn!("Failed to wait on hook for alert '{}': {}", name, e);
+                return;
+            }
+        }
+    }
+}
+
+fn write_fifo(name: &str, path: &Path, env: &[(&str, String)]) {
+    let line = env
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    // Opening a FIFO for writing blocks until a reader turns up, so don't.
+    let result = OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .and_then(|mut fifo| writeln!(fifo, "{}", line));
+
+    if let Err(e) = result {
+        info!(
+            "Could not write alert '{}' to {}: {}",
+            name,
+            path.display(),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::AlertState;
+    use std::env;
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sys-dashboard-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn event() -> AlertEvent {
+        AlertEvent {
+            name: "hot_gpu".to_string(),
+            metric: "gpu.temperature".to_string(),
+            value: 91.,
+            state: AlertState::Firing,
+        }
+    }
+
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "Timed out");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn commands_get_the_alert_in_their_environment() {
+        let dir = temp_dir("hook-env");
+        let out = dir.join("out");
+        let command = format!(
+            "echo \"$SYSDASH_ALERT_NAME $SYSDASH_ALERT_METRIC $SYSDASH_ALERT_VALUE \
+             $SYSDASH_ALERT_STATE\" > {}.partial && mv {0}.partial {0}",
+            out.display()
+        );
+        HookRunner::new(HookSettings::default()).run(&HookAction::Command(command), &event());
+        wait_for(|| out.exists());
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "hot_gpu gpu.temperature 91 firing\n"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commands_are_killed_after_the_timeout() {
+        let started = Instant::now();
+        run_command("slow", "sleep 5", &[], Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn hooks_beyond_the_cap_are_skipped() {
+        let dir = temp_dir("hook-cap");
+        let skipped = dir.join("skipped");
+        let runner = HookRunner::new(HookSettings {
+            timeout_secs: 1,
+            max_concurrent: 1,
+        });
+        runner.run(&HookAction::Command("sleep 5".to_string()), &event());
+        runner.run(
+            &HookAction::Command(format!("touch {}", skipped.display())),
+            &event(),
+        );
+        assert_eq!(runner.running.load(Ordering::SeqCst), 1);
+
+        // The timeout frees the slot for the next one.
+        wait_for(|| runner.running.load(Ordering::SeqCst) == 0);
+        let ran = dir.join("ran");
+        runner.run(
+            &HookAction::Command(format!("touch {}", ran.display())),
+            &event(),
+        );
+        wait_for(|| ran.exists());
+        assert!(!skipped.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fifos_get_one_line_per_alert() {
+        let dir = temp_dir("hook-fifo");
+        let fifo = dir.join("alerts");
+        assert!(Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap()
+            .success());
+
+        // Nobody reading, which mustn't block.
+        write_fifo("hot_gpu", &fifo, &hook_env(&event()));
+
+        let reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&fifo)
+            .unwrap();
+        write_fifo("hot_gpu", &fifo, &hook_env(&event()));
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).unwrap();
+        assert_eq!(
+            line,
+            "SYSDASH_ALERT_NAME=hot_gpu SYSDASH_ALERT_METRIC=gpu.temperature \
+             SYSDASH_ALERT_VALUE=91 SYSDASH_ALERT_STATE=firing\n"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
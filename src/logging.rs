@@ -0,0 +1,308 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Deserialize;
+use simplelog::{
+    CombinedLogger, Config, ConfigBuilder, SharedLogger, TermLogger, TerminalMode, WriteLogger,
+};
+use std::env;
+use std::fs::{create_dir_all, rename, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    pub level: LevelFilter,
+    pub file: Option<PathBuf>,
+    /// Rotate the log file once it grows past this size, 0 to never rotate.
+    pub max_size_kb: u64,
+    /// Number of rotated files (`sys-dash.log.1`, `.2`, ...) to keep.
+    pub keep: u32,
+    pub stderr: bool,
+    pub journald: bool,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Debug,
+            file: None,
+            max_size_kb: 1024,
+            keep: 3,
+            stderr: false,
+            journald: false,
+        }
+    }
+}
+
+impl LogSettings {
+    fn file_path(&self) -> PathBuf {
+        self.file.clone().unwrap_or_else(default_path)
+    }
+}
+
+/// `$XDG_STATE_HOME/sys-dashboard/sys-dash.log`, or `~/.local/state/...`
+/// when `XDG_STATE_HOME` is unset.
+pub fn default_path() -> PathBuf {
+    state_dir().join("sys-dash.log")
+}
+
+pub fn state_dir() -> PathBuf {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_default()
+        .join("sys-dashboard")
+}
+
+/// Sets up every configured log destination. Nothing here is fatal: a log
+/// file that cannot be opened is reported on stderr and logging carries on
+/// to stderr instead.
+pub fn init(settings: &LogSettings) {
+    let config = ConfigBuilder::new().set_time_to_local(true).build();
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![];
+    let mut stderr = settings.stderr;
+
+    let path = settings.file_path();
+    match RotatingFile::open(&path, settings.max_size_kb * 1024, settings.keep) {
+        Ok(file) => loggers.push(WriteLogger::new(settings.level, config.clone(), file)),
+        Err(e) => {
+            eprintln!(
+                "Unable to open log file {}: {}, logging to stderr instead.",
+                path.display(),
+                e
+            );
+            stderr = true;
+        }
+    }
+    if stderr {
+        loggers.push(TermLogger::new(
+            settings.level,
+            config.clone(),
+            TerminalMode::Stderr,
+        ));
+    }
+    if settings.journald {
+        match JournaldLogger::new(settings.level, config) {
+            Ok(logger) => loggers.push(Box::new(logger)),
+            Err(e) => eprintln!("Unable to log to journald: {}", e),
+        }
+    }
+
+    if let Err(e) = CombinedLogger::init(loggers) {
+        eprintln!("Unable to initialize logger: {}", e);
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: u32,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_size: u64, keep: u32) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    fn rotated(&self, n: u32) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..self.keep).rev() {
+            let _ = rename(self.rotated(n), self.rotated(n + 1));
+        }
+        if self.keep > 0 {
+            rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A record larger than max_size on its own still goes into a fresh file
+        // rather than rotating out an empty one. If rotation fails, keep
+        // appending and only try again once another max_size has been written.
+        if self.max_size > 0
+            && self.size > 0
+            && self.size + buf.len() as u64 > self.max_size
+            && self.rotate().is_err()
+        {
+            self.size = 0;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Sends records straight to journald using its native datagram protocol.
+struct JournaldLogger {
+    level: LevelFilter,
+    config: Config,
+    socket: UnixDatagram,
+}
+
+impl JournaldLogger {
+    fn new(level: LevelFilter, config: Config) -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNALD_SOCKET)?;
+        Ok(Self {
+            level,
+            config,
+            socket,
+        })
+    }
+}
+
+impl Log for JournaldLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        // Syslog priorities: err, warning, info, debug.
+        let priority = match record.level() {
+            log::Level::Error => 3,
+            log::Level::Warn => 4,
+            log::Level::Info => 6,
+            log::Level::Debug | log::Level::Trace => 7,
+        };
+        let mut datagram = vec![];
+        journal_field(&mut datagram, "PRIORITY", &priority.to_string());
+        journal_field(&mut datagram, "SYSLOG_IDENTIFIER", "sys-dashboard");
+        journal_field(&mut datagram, "TARGET", record.target());
+        journal_field(&mut datagram, "MESSAGE", &record.args().to_string());
+        let _ = self.socket.send(&datagram);
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for JournaldLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        Some(&self.config)
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+fn journal_field(datagram: &mut Vec<u8>, key: &str, value: &str) {
+    datagram.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, read_to_string};
+    use std::process;
+
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sys-dashboard-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn rotates_and_keeps_the_newest_files() {
+        let dir = log_dir("log-rotate");
+        let path = dir.join("sys-dash.log");
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        for record in &["one\n", "two\n", "three\n", "four\n"] {
+            file.write_all(record.as_bytes()).unwrap();
+        }
+        assert_eq!(read_to_string(&path).unwrap(), "four\n");
+        assert_eq!(
+            read_to_string(dir.join("sys-dash.log.1")).unwrap(),
+            "three\n"
+        );
+        assert_eq!(
+            read_to_string(dir.join("sys-dash.log.2")).unwrap(),
+            "one\ntwo\n"
+        );
+        assert!(!dir.join("sys-dash.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keep_zero_truncates_in_place() {
+        let dir = log_dir("log-keep-zero");
+        let path = dir.join("sys-dash.log");
+        let mut file = RotatingFile::open(&path, 8, 0).unwrap();
+        file.write_all(b"first\n").unwrap();
+        file.write_all(b"second\n").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "second\n");
+        assert!(!dir.join("sys-dash.log.1").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn oversized_records_do_not_rotate_an_empty_file() {
+        let dir = log_dir("log-oversized");
+        let path = dir.join("sys-dash.log");
+        let mut file = RotatingFile::open(&path, 4, 1).unwrap();
+        file.write_all(b"a long record\n").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "a long record\n");
+        assert!(!dir.join("sys-dash.log.1").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_appending_when_rotation_fails() {
+        let dir = log_dir("log-rotate-fails");
+        let path = dir.join("sys-dash.log");
+        let mut file = RotatingFile::open(&path, 8, 1).unwrap();
+        file.write_all(b"first\n").unwrap();
+        // A directory in the way of the rotated name makes the rename fail.
+        fs::create_dir_all(dir.join("sys-dash.log.1").join("x")).unwrap();
+        file.write_all(b"second\n").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "first\nsecond\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,40 +1,123 @@
+mod alert;
+mod bar;
+mod cli;
+mod config;
+mod control;
 mod cpu;
 mod dashboard;
+mod dbus;
+mod energy;
+mod error;
+mod export;
+mod exporter;
 mod fmt;
 mod gpu;
 mod header;
+mod headless;
+mod history;
+mod hook;
+mod logging;
+mod metric;
+mod mqtt;
+mod overview;
+mod paths;
+mod remote;
+mod replay;
+mod runner;
+mod screenshot;
+mod session;
+mod sink;
+mod snapshot;
 mod style;
 mod system;
+mod template;
+mod tui;
+mod units;
 
 extern crate log;
 extern crate simplelog;
 
+use crate::cli::{Mode, Options};
+use crate::config::{Config, Overrides};
 use crate::dashboard::Dashboard;
-use log::LevelFilter;
-use simplelog::{ConfigBuilder, WriteLogger};
-use std::fs::OpenOptions;
+use crate::session::{Player, Recorder};
+use std::path::Path;
+use std::process::exit;
 
 fn main() {
-    init_logger();
+    let options = Options::parse();
 
-    let mut dash = get_dash();
+    init_logger(&options);
 
-    dash.run();
+    match options.mode {
+        Mode::Gui => {
+            let recorder = open_recorder(&options);
+            let player = options.replay.as_deref().map(|path| {
+                Player::load(path, options.speed).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    exit(1);
+                })
+            });
+            let mut dash = get_dash(&options, recorder, player);
 
-    dash.destroy();
+            dash.run();
+
+            dash.destroy();
+        }
+        Mode::Headless => {
+            headless::run(&options.config, &options.overrides, open_recorder(&options))
+        }
+        Mode::Tui => tui::run(&options.config, &options.overrides, open_recorder(&options)),
+        Mode::CheckConfig => check_config(&options.config, &options.overrides),
+        Mode::Bar(format) => bar::run(&options.config, &options.overrides, format),
+        Mode::Snapshot(format) => snapshot::run(&options.config, &options.overrides, format),
+        Mode::Agent { listen } => remote::serve(&options.config, &options.overrides, &listen),
+        Mode::Export {
+            session,
+            output,
+            selection,
+        } => export::run(&session, output.as_deref(), &selection),
+    }
 }
 
-fn init_logger() {
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open("sys-dash.log")
-        .expect("Unable to open log file.");
-    let config = ConfigBuilder::new().set_time_to_local(true).build();
+fn init_logger(options: &Options) {
+    // Problems with the rest of the config get logged once the logger is up.
+    let mut settings = Config::load(&options.config, &options.overrides)
+        .map(|config| config.log)
+        .unwrap_or_default();
+    if let Some(level) = options.log_level {
+        settings.level = level;
+    }
+    if let Some(file) = &options.log_file {
+        settings.file = Some(file.clone());
+    }
+
+    logging::init(&settings);
+}
+
+fn check_config(path: &Path, overrides: &Overrides) {
+    match Config::load(path, overrides) {
+        Ok(_) => println!("{}: ok", path.display()),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
 
-    WriteLogger::init(LevelFilter::Debug, config, file).expect("Unable to initialize logger.");
+fn open_recorder(options: &Options) -> Option<Recorder> {
+    options.record.as_deref().map(|path| {
+        Recorder::create(path, options.record_interval_ms).unwrap_or_else(|e| {
+            eprintln!("Cannot record to {}: {}", path.display(), e);
+            exit(1);
+        })
+    })
 }
 
-fn get_dash() -> Box<Dashboard> {
-    Box::new(Dashboard::new())
+fn get_dash(
+    options: &Options,
+    recorder: Option<Recorder>,
+    player: Option<Player>,
+) -> Box<Dashboard> {
+    Box::new(Dashboard::new(options, recorder, player))
 }
@@ -0,0 +1,123 @@
+use crate::system::{GPUInfo, SystemInfo};
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Metric {
+    pub name: &'static str,
+    /// `None` when the hardware doesn't report this reading.
+    pub value: Option<f64>,
+    pub unit: &'static str,
+}
+
+impl Metric {
+    fn new(name: &'static str, unit: &'static str, value: f64) -> Self {
+        Self {
+            name,
+            value: Some(value),
+            unit,
+        }
+    }
+
+    fn optional<T: Into<f64>>(name: &'static str, unit: &'static str, value: Option<T>) -> Self {
+        Self {
+            name,
+            value: value.map(Into::into),
+            unit,
+        }
+    }
+}
+
+pub fn collect(system_info: &SystemInfo) -> Vec<Metric> {
+    collect_for_gpu(system_info, &system_info.gpu_info)
+}
+
+/// Like `collect`, with the `gpu.*` readings taken from `gpu_info` rather
+/// than the configured card.
+fn collect_for_gpu(system_info: &SystemInfo, gpu_info: &GPUInfo) -> Vec<Metric> {
+    vec![
+        Metric::new("cpu.usage", "%", system_info.cpu_usage as f64),
+        Metric::new("cpu.temperature", "C", system_info.cpu_temp as f64),
+        Metric::new("cpu.freq.avg", "MHz", system_info.avg_cpu_freq() as f64),
+        Metric::new("cpu.freq.max", "MHz", system_info.max_cpu_freq() as f64),
+        Metric::optional("cpu.power.draw", "W", system_info.cpu_power),
+        Metric::new("mem.used", "MiB", system_info.memory_info.used_mib() as f64),
+        Metric::new(
+            "mem.total",
+            "MiB",
+            system_info.memory_info.total_mib() as f64,
+        ),
+        Metric::optional("gpu.temperature", "C", gpu_info.temperature),
+        Metric::optional("gpu.utilization", "%", gpu_info.utilization),
+        Metric::optional("gpu.memory.used", "MiB", gpu_info.used_memory),
+        Metric::optional("gpu.memory.total", "MiB", gpu_info.total_memory),
+        Metric::optional("gpu.power.draw", "W", gpu_info.power_draw),
+        Metric::optional("gpu.power.limit", "W", gpu_info.power_limit),
+        Metric::optional("energy.session", "Wh", system_info.energy.session_wh),
+        Metric::optional("energy.today", "Wh", system_info.energy.today_wh),
+        Metric::optional("energy.today.cost", "", system_info.energy.today_cost),
+    ]
+}
+
+/// The reading called `name`. Besides the names `collect` returns, this
+/// takes `gpu.<n>.<field>` for any card, `gpu.1.temperature` being the
+/// second card's `gpu.temperature`, and `temp` for `temperature`.
+pub fn lookup(system_info: &SystemInfo, name: &str) -> Option<f64> {
+    let (card, name) = resolve(name);
+    let metrics = match card {
+        Some(index) => collect_for_gpu(system_info, system_info.gpus.get(index)?),
+        None => collect(system_info),
+    };
+    metrics
+        .into_iter()
+        .find(|m| m.name == name)
+        .and_then(|m| m.value)
+}
+
+/// `name` as `collect` calls it, and the card a `gpu.<n>.<field>` name
+/// reads.
+pub(crate) fn resolve(name: &str) -> (Option<usize>, String) {
+    let name = match name.strip_suffix(".temp") {
+        Some(base) => format!("{}.temperature", base),
+        None => name.to_string(),
+    };
+    let card = name
+        .strip_prefix("gpu.")
+        .and_then(|rest| rest.split_once('.'))
+        .and_then(|(index, field)| Some((index.parse().ok()?, format!("gpu.{}", field))));
+    match card {
+        Some((index, name)) => (Some(index), name),
+        None => (None, name),
+    }
+}
+
+pub fn names() -> Vec<&'static str> {
+    collect(&SystemInfo::default())
+        .iter()
+        .map(|m| m.name)
+        .collect()
+}
+
+pub fn is_known(name: &str) -> bool {
+    names().contains(&resolve(name).1.as_str())
+}
+
+/// Readings that are text rather than numbers, which only templates show.
+pub const TEXT: [&str; 2] = ["cpu.name", "gpu.name"];
+
+pub fn is_text(name: &str) -> bool {
+    TEXT.contains(&resolve(name).1.as_str())
+}
+
+/// The text reading called `name`, taking the same forms as `lookup`.
+pub fn lookup_text(system_info: &SystemInfo, name: &str) -> Option<String> {
+    let (card, name) = resolve(name);
+    let gpu_info = match card {
+        Some(index) => system_info.gpus.get(index)?,
+        None => &system_info.gpu_info,
+    };
+    match name.as_str() {
+        "cpu.name" => Some(system_info.cpu_name.trim().to_string()),
+        "gpu.name" => Some(gpu_info.name.clone()),
+        _ => None,
+    }
+}
@@ -0,0 +1,604 @@
+use crate::metric;
+use crate::system::SystemInfo;
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+const KEEP_ALIVE_SECS: u16 = 60;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttSettings {
+    pub enabled: bool,
+    /// `host:port` of the broker.
+    pub broker: String,
+    /// Defaults to `sys-dashboard-<hostname>`.
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Metrics go to `<topic_prefix>/<hostname>/cpu/usage` and so on.
+    pub topic_prefix: String,
+    /// 0 (at most once) or 1 (at least once).
+    pub qos: u8,
+    pub retain: bool,
+    pub interval_ms: u64,
+    /// Announce every metric as a Home Assistant sensor.
+    pub discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            broker: "127.0.0.1:1883".to_string(),
+            client_id: None,
+            username: None,
+            password: None,
+            topic_prefix: "sysdash".to_string(),
+            qos: 0,
+            retain: true,
+            interval_ms: 10_000,
+            discovery: false,
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+impl MqttSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.qos > 1 {
+            return Err(format!("mqtt.qos must be 0 or 1, got {}", self.qos));
+        }
+        if self.interval_ms == 0 {
+            return Err("mqtt.interval_ms must be at least 1".to_string());
+        }
+        if self.password.is_some() && self.username.is_none() {
+            return Err("mqtt.password needs a username".to_string());
+        }
+        Ok(())
+    }
+}
+
+struct Message {
+    topic: String,
+    payload: String,
+    retain: bool,
+}
+
+/// Publishes every metric to its own topic on an MQTT broker. Like the
+/// other sinks the connection lives on its own thread, a reading that
+/// can't be delivered is superseded by the next one rather than queued.
+pub struct Mqtt {
+    settings: MqttSettings,
+    tx: Sender<(String, Vec<Message>)>,
+    last_push: Option<Instant>,
+}
+
+impl Mqtt {
+    pub fn new(settings: &MqttSettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        let (tx, rx) = channel();
+        let client = Client::new(settings.clone());
+        thread::spawn(move || client.run(rx));
+        Some(Self {
+            settings: settings.clone(),
+            tx,
+            last_push: None,
+        })
+    }
+
+    pub fn publish(&mut self, system_info: &SystemInfo) {
+        let interval = Duration::from_millis(self.settings.interval_ms);
+        if self.last_push.is_some_and(|last| last.elapsed() < interval) {
+            return;
+        }
+        self.last_push = Some(Instant::now());
+
+        let base = base_topic(&self.settings, &system_info.host);
+        let messages = metric::collect(system_info)
+            .into_iter()
+            .filter_map(|m| {
+                m.value.map(|value| Message {
+                    topic: format!("{}/{}", base, m.name.replace('.', "/")),
+                    payload: value.to_string(),
+                    retain: self.settings.retain,
+                })
+            })
+            .collect();
+        let _ = self.tx.send((system_info.host.clone(), messages));
+    }
+}
+
+fn base_topic(settings: &MqttSettings, host: &str) -> String {
+    format!("{}/{}", settings.topic_prefix, topic_safe(host))
+}
+
+/// `+`, `#` and `/` mean something in topics, and Home Assistant ids only
+/// allow `[a-zA-Z0-9_-]`.
+fn topic_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Retained config messages that make Home Assistant create one sensor per
+/// metric, grouped under a device for the host.
+fn discovery(settings: &MqttSettings, host: &str) -> Vec<Message> {
+    let node = topic_safe(host);
+    let base = base_topic(settings, host);
+    metric::collect(&SystemInfo::default())
+        .into_iter()
+        .map(|m| {
+            let object = m.name.replace('.', "_");
+            let (unit, device_class) = match m.unit {
+                "C" => ("°C", Some("temperature")),
+                "W" => ("W", Some("power")),
+                "MHz" => ("MHz", Some("frequency")),
+                unit => (unit, None),
+            };
+            let mut config = json!({
+                "name": m.name,
+                "unique_id": format!("sysdash_{}_{}", node, object),
+                "state_topic": format!("{}/{}", base, m.name.replace('.', "/")),
+                "availability_topic": format!("{}/status", base),
+                "unit_of_measurement": unit,
+                "state_class": "measurement",
+                "device": {
+                    "identifiers": [format!("sysdash_{}", node)],
+                    "name": host,
+                    "model": "sys-dashboard",
+                },
+            });
+            if let Some(device_class) = device_class {
+                config["device_class"] = json!(device_class);
+            }
+            Message {
+                topic: format!(
+                    "{}/sensor/{}/{}/config",
+                    settings.discovery_prefix, node, object
+                ),
+                payload: config.to_string(),
+                retain: true,
+            }
+        })
+        .collect()
+}
+
+/// A minimal MQTT 3.1.1 client: connect, publish at QoS 0 or 1, keep alive.
+struct Client {
+    settings: MqttSettings,
+    stream: Option<TcpStream>,
+    /// Set once connected, for the goodbye message.
+    host: Option<String>,
+    packet_id: u16,
+    failing: bool,
+}
+
+impl Client {
+    fn new(settings: MqttSettings) -> Self {
+        Self {
+            settings,
+            stream: None,
+            host: None,
+            packet_id: 0,
+            failing: false,
+        }
+    }
+
+    fn run(mut self, rx: Receiver<(String, Vec<Message>)>) {
+        let keep_alive = Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2);
+        loop {
+            match rx.recv_timeout(keep_alive) {
+                Ok((host, messages)) => {
+                    if let Err(e) = self.deliver(&host, &messages) {
+                        self.stream = None;
+                        if !self.failing {
+                            self.failing = true;
+                            warn!("MQTT broker {} unavailable: {}", self.settings.broker, e);
+                        }
+                    } else if self.failing {
+                        self.failing = false;
+                        info!("MQTT broker {} is reachable again", self.settings.broker);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(e) = self.ping() {
+                        warn!(
+                            "MQTT broker {} stopped answering: {}",
+                            self.settings.broker, e
+                        );
+                        self.stream = None;
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        // A clean disconnect suppresses the will, so say goodbye ourselves.
+        if let Some(host) = self.host.take() {
+            let offline = Message {
+                topic: format!("{}/status", base_topic(&self.settings, &host)),
+                payload: "offline".to_string(),
+                retain: true,
+            };
+            let _ = self.send_publish(&offline);
+            let _ = self.write(&[0xe0, 0x00]);
+        }
+    }
+
+    fn deliver(&mut self, host: &str, messages: &[Message]) -> io::Result<()> {
+        if self.stream.is_none() {
+            self.connect(host)?;
+        }
+        for message in messages {
+            self.send_publish(message)?;
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, host: &str) -> io::Result<()> {
+        let addr = self
+            .settings
+            .broker
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "broker did not resolve"))?;
+        let stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        self.stream = Some(stream);
+
+        let status = format!("{}/status", base_topic(&self.settings, host));
+        let client_id = self
+            .settings
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("sys-dashboard-{}", topic_safe(host)));
+
+        // Clean session, a retained "offline" will at QoS 0.
+        let mut flags = 0x02 | 0x04 | 0x20;
+        let mut payload = vec![];
+        put_str(&mut payload, &client_id);
+        put_str(&mut payload, &status);
+        put_str(&mut payload, "offline");
+        if let Some(username) = &self.settings.username {
+            flags |= 0x80;
+            put_str(&mut payload, username);
+        }
+        if let Some(password) = &self.settings.password {
+            flags |= 0x40;
+            put_str(&mut payload, password);
+        }
+        let mut body = vec![];
+        put_str(&mut body, "MQTT");
+        body.push(4);
+        body.push(flags);
+        body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+        body.extend(payload);
+        self.write(&packet(0x10, &body))?;
+
+        let (kind, body) = self.read()?;
+        if kind != 0x20 || body.len() != 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected CONNACK",
+            ));
+        }
+        let refused = match body[1] {
+            0 => None,
+            1 => Some("unacceptable protocol version"),
+            2 => Some("client id rejected"),
+            3 => Some("server unavailable"),
+            4 => Some("bad user name or password"),
+            5 => Some("not authorized"),
+            _ => Some("refused"),
+        };
+        if let Some(reason) = refused {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, reason));
+        }
+        info!("Connected to MQTT broker {}", self.settings.broker);
+        self.host = Some(host.to_string());
+
+        self.send_publish(&Message {
+            topic: status,
+            payload: "online".to_string(),
+            retain: true,
+        })?;
+        if self.settings.discovery {
+            for message in discovery(&self.settings, host) {
+                self.send_publish(&message)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send_publish(&mut self, message: &Message) -> io::Result<()> {
+        let qos = self.settings.qos;
+        let mut body = vec![];
+        put_str(&mut body, &message.topic);
+        let packet_id = if qos > 0 {
+            self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+            body.extend_from_slice(&self.packet_id.to_be_bytes());
+            Some(self.packet_id)
+        } else {
+            None
+        };
+        body.extend_from_slice(message.payload.as_bytes());
+        let header = 0x30 | (qos << 1) | if message.retain { 0x01 } else { 0x00 };
+        self.write(&packet(header, &body))?;
+
+        if let Some(packet_id) = packet_id {
+            loop {
+                let (kind, body) = self.read()?;
+                // PINGRESPs may still be in flight, skip anything else.
+                if kind == 0x40 && body == packet_id.to_be_bytes() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn ping(&mut self) -> io::Result<()> {
+        if self.stream.is_none() {
+            return Ok(());
+        }
+        self.write(&[0xc0, 0x00])?;
+        loop {
+            let (kind, _) = self.read()?;
+            if kind == 0xd0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.stream {
+            Some(stream) => stream.write_all(bytes),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "not connected")),
+        }
+    }
+
+    /// Reads one packet, returning its type nibble and body.
+    fn read(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => return Err(io::Error::new(io::ErrorKind::NotConnected, "not connected")),
+        };
+        let mut byte = [0; 1];
+        stream.read_exact(&mut byte)?;
+        let kind = byte[0] & 0xf0;
+        let mut len = 0usize;
+        for shift in (0..28).step_by(7) {
+            stream.read_exact(&mut byte)?;
+            len |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body)?;
+        Ok((kind, body))
+    }
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// A PUBLISH as the broker saw it.
+    #[derive(Debug)]
+    struct Published {
+        topic: String,
+        payload: String,
+        qos: u8,
+        retain: bool,
+        packet_id: Option<u16>,
+    }
+
+    fn take_str(body: &mut &[u8]) -> String {
+        let len = u16::from_be_bytes([body[0], body[1]]) as usize;
+        let s = String::from_utf8(body[2..2 + len].to_vec()).unwrap();
+        *body = &body[2 + len..];
+        s
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut byte = [0; 1];
+        stream.read_exact(&mut byte).ok()?;
+        let header = byte[0];
+        let mut len = 0usize;
+        for shift in (0..28).step_by(7) {
+            stream.read_exact(&mut byte).ok()?;
+            len |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).ok()?;
+        Some((header, body))
+    }
+
+    /// Accepts one client, answers its CONNECT and acknowledges QoS 1
+    /// publishes, first with a PUBACK for another packet the client must
+    /// skip. Hands over every packet it gets.
+    fn broker() -> (String, Receiver<(u8, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            while let Some((header, body)) = read_packet(&mut stream) {
+                match header & 0xf0 {
+                    0x10 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap(),
+                    0x30 if header & 0x06 != 0 => {
+                        let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                        let id = u16::from_be_bytes([body[2 + topic_len], body[3 + topic_len]]);
+                        let other = id.wrapping_add(1000).to_be_bytes();
+                        stream.write_all(&[0x40, 0x02, other[0], other[1]]).unwrap();
+                        let id = id.to_be_bytes();
+                        stream.write_all(&[0x40, 0x02, id[0], id[1]]).unwrap();
+                    }
+                    _ => {}
+                }
+                if tx.send((header, body)).is_err() {
+                    return;
+                }
+            }
+        });
+        (address, rx)
+    }
+
+    fn publish(header: u8, body: &[u8]) -> Published {
+        let mut body = body;
+        let topic = take_str(&mut body);
+        let qos = (header >> 1) & 0x03;
+        let packet_id = if qos > 0 {
+            let id = u16::from_be_bytes([body[0], body[1]]);
+            body = &body[2..];
+            Some(id)
+        } else {
+            None
+        };
+        Published {
+            topic,
+            payload: String::from_utf8(body.to_vec()).unwrap(),
+            qos,
+            retain: header & 0x01 != 0,
+            packet_id,
+        }
+    }
+
+    #[test]
+    fn connects_announces_and_publishes() {
+        let (address, packets) = broker();
+        let settings = MqttSettings {
+            enabled: true,
+            broker: address,
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            qos: 1,
+            discovery: true,
+            ..MqttSettings::default()
+        };
+        let mut mqtt = Mqtt::new(&settings).unwrap();
+        let mut system_info = SystemInfo::default();
+        system_info.host = "box.lan".to_string();
+        system_info.cpu_usage = 42;
+        mqtt.publish(&system_info);
+
+        let timeout = Duration::from_secs(5);
+        let (header, body) = packets.recv_timeout(timeout).unwrap();
+        assert_eq!(header, 0x10);
+        let mut rest = &body[..];
+        assert_eq!(take_str(&mut rest), "MQTT");
+        // Level 4, user name, password, retained will, clean session and
+        // the keep alive.
+        assert_eq!(rest[..4], [4, 0xe6, 0, 60]);
+        rest = &rest[4..];
+        assert_eq!(take_str(&mut rest), "sys-dashboard-box_lan");
+        assert_eq!(take_str(&mut rest), "sysdash/box_lan/status");
+        assert_eq!(take_str(&mut rest), "offline");
+        assert_eq!(take_str(&mut rest), "user");
+        assert_eq!(take_str(&mut rest), "secret");
+
+        let mut published = vec![];
+        while !published
+            .iter()
+            .any(|p: &Published| p.topic == "sysdash/box_lan/cpu/usage")
+        {
+            let (header, body) = packets.recv_timeout(timeout).unwrap();
+            assert_eq!(header & 0xf0, 0x30);
+            published.push(publish(header, &body));
+        }
+
+        let status = &published[0];
+        assert_eq!(
+            (status.topic.as_str(), status.payload.as_str()),
+            ("sysdash/box_lan/status", "online")
+        );
+        assert!(status.retain);
+        // Every publish waited for its own PUBACK before the next.
+        let ids = published.iter().map(|p| p.packet_id).collect::<Vec<_>>();
+        let expected = (1..=published.len() as u16).map(Some).collect::<Vec<_>>();
+        assert_eq!(ids, expected);
+        assert!(published.iter().all(|p| p.qos == 1));
+
+        let config = published
+            .iter()
+            .find(|p| p.topic == "homeassistant/sensor/box_lan/cpu_usage/config")
+            .unwrap();
+        assert!(config.retain);
+        let config: serde_json::Value = serde_json::from_str(&config.payload).unwrap();
+        assert_eq!(config["state_topic"], "sysdash/box_lan/cpu/usage");
+        assert_eq!(config["availability_topic"], "sysdash/box_lan/status");
+        assert_eq!(config["unique_id"], "sysdash_box_lan_cpu_usage");
+        let temperature = published
+            .iter()
+            .find(|p| p.topic == "homeassistant/sensor/box_lan/gpu_temperature/config")
+            .unwrap();
+        assert!(temperature
+            .payload
+            .contains("\"device_class\":\"temperature\""));
+
+        let usage = published.last().unwrap();
+        assert_eq!(usage.payload, "42");
+        assert!(usage.retain);
+
+        // Going away says so and disconnects cleanly.
+        drop(mqtt);
+        let mut last = vec![];
+        while let Ok((header, body)) = packets.recv_timeout(timeout) {
+            last.push((header, body));
+            if header == 0xe0 {
+                break;
+            }
+        }
+        let (header, body) = &last[last.len() - 2];
+        let offline = publish(*header, body);
+        assert_eq!(
+            (offline.topic.as_str(), offline.payload.as_str()),
+            ("sysdash/box_lan/status", "offline")
+        );
+        assert_eq!(last.last().unwrap().0, 0xe0);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::config::Config;
+use crate::error::Source;
+use crate::fmt::{create_label, get_session_name, show_problems, Name};
+use crate::system::SystemInfo;
+use gdk::prelude::IsA;
+use gtk::prelude::*;
+use gtk::{Align, Orientation, Widget};
+
+/// A compact tile for every connected host, clicking one opens its full
+/// dashboard.
+pub struct OverviewView {
+    container: gtk::FlowBox,
+    tiles: Vec<Tile>,
+}
+
+struct Tile {
+    button: gtk::Button,
+    address: String,
+    host: gtk::Label,
+    cpu: gtk::Label,
+    ram: gtk::Label,
+    gpu: gtk::Label,
+    alerts: gtk::Label,
+    status: gtk::Label,
+}
+
+impl OverviewView {
+    pub fn new(addresses: &[String]) -> Self {
+        let container = gtk::FlowBoxBuilder::new()
+            .selection_mode(gtk::SelectionMode::None)
+            .homogeneous(true)
+            .row_spacing(12)
+            .column_spacing(12)
+            .valign(Align::Start)
+            .build();
+        let tiles = addresses
+            .iter()
+            .map(|address| {
+                let tile = Tile::new(address);
+                container.add(&tile.button);
+                tile
+            })
+            .collect();
+        Self { container, tiles }
+    }
+
+    /// Calls `on_select` with the index of the host whose tile was clicked.
+    pub fn connect_selected<F: Fn(usize) + Clone + 'static>(&self, on_select: F) {
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let on_select = on_select.clone();
+            tile.button.connect_clicked(move |_| on_select(index));
+        }
+    }
+
+    pub fn update(&self, index: usize, system_info: &SystemInfo, firing: &[&str], config: &Config) {
+        if let Some(tile) = self.tiles.get(index) {
+            tile.update(system_info, firing, config);
+        }
+    }
+
+    pub(super) fn widget(&self) -> &impl IsA<Widget> {
+        &self.container
+    }
+}
+
+impl Tile {
+    fn new(address: &str) -> Self {
+        let host = create_label("tile_host", Align::Start);
+        host.set_text(address);
+        let cpu = create_label("tile_cpu", Align::Start);
+        let ram = create_label("tile_ram", Align::Start);
+        let gpu = create_label("tile_gpu", Align::Start);
+        let alerts = create_label("tile_alerts", Align::Start);
+        let status = create_label("status", Align::Start);
+        status.set_no_show_all(true);
+
+        let container = gtk::BoxBuilder::new()
+            .orientation(Orientation::Vertical)
+            .spacing(4)
+            .build();
+        for label in &[&host, &cpu, &ram, &gpu, &alerts, &status] {
+            container.pack_start(*label, false, false, 0);
+        }
+        let button = gtk::ButtonBuilder::new()
+            .relief(gtk::ReliefStyle::None)
+            .build();
+        button.get_style_context().add_class("tile");
+        button.add(&container);
+
+        Self {
+            button,
+            address: address.to_string(),
+            host,
+            cpu,
+            ram,
+            gpu,
+            alerts,
+            status,
+        }
+    }
+
+    fn update(&self, system_info: &SystemInfo, firing: &[&str], config: &Config) {
+        // Nothing has arrived from the agent yet.
+        if system_info.host.is_empty() {
+            self.host.set_text(&self.address);
+        } else {
+            self.host
+                .set_text(&get_session_name(&system_info.user, &system_info.host));
+        }
+        let labels = &config.labels;
+        self.cpu
+            .set_text(&labels.tile_cpu.render(system_info, &config.units));
+        self.ram
+            .set_text(&labels.tile_ram.render(system_info, &config.units));
+        // One row per card, or one saying the readings aren't available.
+        let gpus = if system_info.gpus.is_empty() {
+            labels.tile_gpu.render(system_info, &config.units)
+        } else {
+            (0..system_info.gpus.len())
+                .map(|index| {
+                    labels
+                        .tile_gpu
+                        .for_gpu(index)
+                        .render(system_info, &config.units)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        self.gpu.set_text(&gpus);
+        self.alerts.set_text(
+            &if firing.is_empty() {
+                "ok".to_string()
+            } else {
+                firing.join(", ")
+            }
+            .as_field_name("Alerts"),
+        );
+        if firing.is_empty() {
+            self.button.get_style_context().remove_class("firing");
+        } else {
+            self.button.get_style_context().add_class("firing");
+        }
+        show_problems(&self.status, &system_info.problems(&[Source::Connection]));
+    }
+}
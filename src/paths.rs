@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Where to find procfs, sysfs and /etc. Everything lives under `root`
+/// unless a directory is given explicitly, which allows reading a
+/// container's or chroot's view, or a captured snapshot from `fixtures/`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostPaths {
+    pub root: PathBuf,
+    pub proc: Option<PathBuf>,
+    pub sys: Option<PathBuf>,
+    pub etc: Option<PathBuf>,
+}
+
+impl Default for HostPaths {
+    fn default() -> Self {
+        Self::rooted(Path::new("/"))
+    }
+}
+
+impl HostPaths {
+    pub fn rooted(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            proc: None,
+            sys: None,
+            etc: None,
+        }
+    }
+
+    fn proc(&self) -> PathBuf {
+        self.proc.clone().unwrap_or_else(|| self.root.join("proc"))
+    }
+
+    fn sys(&self) -> PathBuf {
+        self.sys.clone().unwrap_or_else(|| self.root.join("sys"))
+    }
+
+    fn etc(&self) -> PathBuf {
+        self.etc.clone().unwrap_or_else(|| self.root.join("etc"))
+    }
+
+    pub fn stat(&self) -> PathBuf {
+        self.proc().join("stat")
+    }
+
+    pub fn meminfo(&self) -> PathBuf {
+        self.proc().join("meminfo")
+    }
+
+    pub fn cpuinfo(&self) -> PathBuf {
+        self.proc().join("cpuinfo")
+    }
+
+    pub fn thermal_zone_temp(&self, zone: u32) -> PathBuf {
+        self.sys()
+            .join("class/thermal")
+            .join(format!("thermal_zone{}", zone))
+            .join("temp")
+    }
+
+    pub fn powercap(&self) -> PathBuf {
+        self.sys().join("class/powercap")
+    }
+
+    /// /etc/os-release, or /usr/lib/os-release on systems without the former.
+    pub fn os_release(&self) -> PathBuf {
+        let etc = self.etc().join("os-release");
+        if etc.exists() {
+            etc
+        } else {
+            self.root.join("usr/lib/os-release")
+        }
+    }
+}
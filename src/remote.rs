@@ -0,0 +1,771 @@
+use crate::config::{Config, Overrides};
+use crate::error::{CollectorError, Source};
+use crate::headless;
+use crate::session;
+use crate::snapshot::Snapshot;
+use crate::system::SystemInfo;
+use log::{error, info, warn};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    AlertDescription, CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct,
+    RootCertStore, ServerConfig, ServerConnection, SignatureScheme, StreamOwned,
+};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError,
+};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Sent by the agent when a dashboard connects, followed by the protocol
+/// version as a big-endian u16. The dashboard answers with its token as a
+/// frame, empty when it has none, and the agent with `ACCEPTED` or
+/// `REJECTED`. Every frame is a big-endian u32 length and that many bytes,
+/// the ones after that hold a JSON `Snapshot`, or nothing when the agent
+/// only shows it's still there.
+const MAGIC: &[u8] = b"SYSDASH";
+pub const PROTOCOL_VERSION: u16 = 3;
+const ACCEPTED: u8 = 0;
+const REJECTED: u8 = 1;
+/// Far more than any snapshot, but stops a confused peer from making us
+/// allocate gigabytes.
+const MAX_FRAME: u32 = 16 * 1024 * 1024;
+const MAX_TOKEN: u32 = 4096;
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the agent stays quiet before sending an empty frame, so a
+/// dashboard can tell a slow refresh interval from a dead connection.
+const KEEPALIVE: Duration = Duration::from_secs(5);
+const RETRY: Duration = Duration::from_secs(2);
+
+/// How `sys-dashboard agent` accepts dashboards. Without a certificate it
+/// streams in the clear to anyone who can reach it.
+#[derive(Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentSettings {
+    /// PEM certificate chain and private key to serve TLS with.
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// Only admit dashboards presenting a certificate issued by one in this
+    /// PEM file, or a self-signed one listed in it. Self-signed dashboard
+    /// certificates must be made with `basicConstraints=CA:FALSE`.
+    pub client_ca: Option<PathBuf>,
+    /// Only admit dashboards sending this token.
+    pub token: Option<String>,
+}
+
+impl AgentSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cert.is_some() != self.key.is_some() {
+            return Err("agent.cert and agent.key must be set together".to_string());
+        }
+        if self.cert.is_none() && (self.client_ca.is_some() || self.token.is_some()) {
+            return Err(
+                "agent.client_ca and agent.token need agent.cert and agent.key".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// How the dashboard connects to the agent at `address`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteSettings {
+    /// Matched against the `--connect` address.
+    pub address: String,
+    /// Connect over TLS, trusting only an agent presenting exactly this PEM
+    /// certificate.
+    pub server_cert: Option<PathBuf>,
+    /// PEM certificate chain and private key, for agents with a
+    /// `client_ca`.
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub token: Option<String>,
+}
+
+impl RemoteSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.cert.is_some() != self.key.is_some() {
+            return Err(format!(
+                "remote '{}' must set cert and key together",
+                self.address
+            ));
+        }
+        if self.server_cert.is_none() && (self.cert.is_some() || self.token.is_some()) {
+            return Err(format!(
+                "remote '{}' needs a server_cert to send a certificate or token",
+                self.address
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What dashboards and agents talk over, plain TCP or TLS.
+trait Stream: Read + Write + Send {}
+
+impl<S: Read + Write + Send> Stream for S {}
+
+/// How many frames may wait for a dashboard before it misses some.
+const BACKLOG: usize = 2;
+
+/// Runs the collectors like `--headless` and streams every reading to the
+/// dashboards connected to `listen`.
+pub fn serve(config_path: &Path, overrides: &Overrides, listen: &str) {
+    // Falling back to the defaults could mean serving in the clear.
+    let settings = Config::load(config_path, overrides)
+        .map(|config| config.agent)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            exit(1);
+        });
+    let tls = server_tls(&settings).unwrap_or_else(|e| {
+        eprintln!("Cannot set up TLS: {}", e);
+        exit(1);
+    });
+    let listener = TcpListener::bind(listen).unwrap_or_else(|e| {
+        eprintln!("Cannot listen on {}: {}", listen, e);
+        exit(1);
+    });
+    match &tls {
+        Some(_) => info!("Agent listening on {} with TLS", listen),
+        None => warn!("Agent listening on {} without TLS", listen),
+    }
+
+    let dashboards = Dashboards::accept(listener, tls, settings.token);
+    headless::run_with(config_path, overrides, |system_info, _| {
+        dashboards.send(frame(system_info));
+    });
+}
+
+/// A frame shared by every dashboard's queue.
+type Frame = Arc<Vec<u8>>;
+
+/// The dashboards connected to an agent. Each is written to from its own
+/// thread, so one on a slow link can't hold up the others.
+struct Dashboards {
+    queues: Arc<Mutex<Vec<SyncSender<Frame>>>>,
+}
+
+impl Dashboards {
+    fn accept(
+        listener: TcpListener,
+        tls: Option<Arc<ServerConfig>>,
+        token: Option<String>,
+    ) -> Self {
+        let queues = Arc::new(Mutex::new(Vec::new()));
+        let accepted = queues.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Cannot accept dashboard: {}", e);
+                        continue;
+                    }
+                };
+                // A slow handshake mustn't hold up the next dashboard.
+                let tls = tls.clone();
+                let token = token.clone();
+                let accepted = accepted.clone();
+                thread::spawn(move || {
+                    let peer = peer(&stream);
+                    match admit(stream, tls, token.as_deref()) {
+                        Ok(stream) => {
+                            info!("Dashboard connected from {}", peer);
+                            let (queue, frames) = sync_channel(BACKLOG);
+                            accepted
+                                .lock()
+                                .expect("Dashboard list poisoned")
+                                .push(queue);
+                            write_frames(&peer, stream, frames);
+                        }
+                        Err(e) => warn!("Rejected dashboard {}: {}", peer, e),
+                    }
+                });
+            }
+        });
+        Self { queues }
+    }
+
+    /// Queues `frame` for every dashboard. One that is still behind misses
+    /// it and catches up with the next.
+    fn send(&self, frame: Vec<u8>) {
+        let frame = Arc::new(frame);
+        self.queues
+            .lock()
+            .expect("Dashboard list poisoned")
+            .retain(|queue| {
+                !matches!(
+                    queue.try_send(frame.clone()),
+                    Err(TrySendError::Disconnected(_))
+                )
+            });
+    }
+}
+
+/// Writes one dashboard's frames until it goes away, and an empty one
+/// whenever there was nothing to send for `KEEPALIVE`. A dashboard that
+/// can't take a frame within the timeout is dropped, it reconnects and
+/// starts from the latest reading.
+fn write_frames(peer: &str, mut stream: Box<dyn Stream>, frames: Receiver<Frame>) {
+    let keepalive = 0u32.to_be_bytes();
+    loop {
+        let frame = match frames.recv_timeout(KEEPALIVE) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => Arc::new(keepalive.to_vec()),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if let Err(e) = stream.write_all(&frame).and_then(|()| stream.flush()) {
+            info!("Dashboard {} disconnected: {}", peer, e);
+            return;
+        }
+    }
+}
+
+fn admit(
+    tcp: TcpStream,
+    tls: Option<Arc<ServerConfig>>,
+    token: Option<&str>,
+) -> io::Result<Box<dyn Stream>> {
+    tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_nodelay(true)?;
+    let mut stream: Box<dyn Stream> = match tls {
+        Some(config) => Box::new(StreamOwned::new(
+            ServerConnection::new(config).map_err(io::Error::other)?,
+            tcp,
+        )),
+        None => Box::new(tcp),
+    };
+    stream.write_all(MAGIC)?;
+    stream.write_all(&PROTOCOL_VERSION.to_be_bytes())?;
+    stream.flush()?;
+
+    let presented = read_frame(&mut stream, MAX_TOKEN)?;
+    let admitted = token.is_none_or(|token| same(token.as_bytes(), &presented));
+    stream.write_all(&[if admitted { ACCEPTED } else { REJECTED }])?;
+    stream.flush()?;
+    if !admitted {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "wrong token",
+        ));
+    }
+    Ok(stream)
+}
+
+/// Compares in constant time, so the token can't be guessed byte by byte.
+fn same(expected: &[u8], presented: &[u8]) -> bool {
+    expected.len() == presented.len()
+        && expected
+            .iter()
+            .zip(presented)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn peer(stream: &TcpStream) -> String {
+    stream
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn frame(system_info: &SystemInfo) -> Vec<u8> {
+    let json = serde_json::to_vec(&Snapshot::new(system_info)).expect("Snapshots always serialize");
+    let mut frame = (json.len() as u32).to_be_bytes().to_vec();
+    frame.extend(json);
+    frame
+}
+
+fn read_frame<S: Read + ?Sized>(stream: &mut S, max: u32) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len);
+    if len > max {
+        return Err(invalid(format!("frame of {} bytes is too large", len)));
+    }
+    let mut bytes = vec![0; len as usize];
+    stream.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn server_tls(settings: &AgentSettings) -> io::Result<Option<Arc<ServerConfig>>> {
+    let (cert, key) = match (&settings.cert, &settings.key) {
+        (Some(cert), Some(key)) => (cert, key),
+        _ => return Ok(None),
+    };
+    let builder = ServerConfig::builder();
+    let builder = match &settings.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca)? {
+                roots.add(cert).map_err(|e| unreadable(client_ca, e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|e| unreadable(client_ca, e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|e| unreadable(key, e))?;
+    Ok(Some(Arc::new(config)))
+}
+
+fn client_tls(settings: &RemoteSettings) -> io::Result<Option<Arc<ClientConfig>>> {
+    let server_cert = match &settings.server_cert {
+        Some(server_cert) => server_cert,
+        None => return Ok(None),
+    };
+    let pinned = load_certs(server_cert)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| unreadable(server_cert, "no certificate in file"))?;
+    let builder = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(Pinned::new(pinned)));
+    let config = match (&settings.cert, &settings.key) {
+        (Some(cert), Some(key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(|e| unreadable(key, e))?,
+        _ => builder.with_no_client_auth(),
+    };
+    Ok(Some(Arc::new(config)))
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect())
+        .map_err(|e| unreadable(path, e))
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path).map_err(|e| unreadable(path, e))
+}
+
+fn unreadable<E: std::fmt::Display>(path: &Path, e: E) -> io::Error {
+    io::Error::other(format!("{}: {}", path.display(), e))
+}
+
+/// Trusts exactly one certificate, whoever issued it and whatever name or
+/// expiry it carries, the way a pinned SSH host key works.
+#[derive(Debug)]
+struct Pinned {
+    cert: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl Pinned {
+    fn new(cert: CertificateDer<'static>) -> Self {
+        Self {
+            cert,
+            provider: Arc::new(rustls::crypto::ring::default_provider()),
+        }
+    }
+}
+
+impl ServerCertVerifier for Pinned {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if *end_entity == self.cert {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(CertificateError::ApplicationVerificationFailure.into())
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Readings streamed from an agent, for a dashboard started with
+/// `--connect`. Reconnects by itself whenever the connection drops.
+pub struct Remote {
+    rx: Receiver<Result<SystemInfo, CollectorError>>,
+}
+
+impl Remote {
+    /// Connects in the clear unless `settings` pins the agent's certificate.
+    pub fn connect(address: &str, settings: Option<RemoteSettings>) -> Self {
+        let (tx, rx) = channel();
+        let address = address.to_string();
+        thread::spawn(move || loop {
+            // The certificates are re-read on every attempt, so fixing them
+            // doesn't need a restart.
+            let tls = match &settings {
+                Some(settings) => client_tls(settings),
+                None => Ok(None),
+            };
+            let token = settings.as_ref().and_then(|s| s.token.as_deref());
+            if let Err(e) = tls.and_then(|tls| stream_from(&address, tls, token, &tx)) {
+                let e = CollectorError::Unreachable(address.clone(), explain(&e));
+                if tx.send(Err(e)).is_err() {
+                    return;
+                }
+            }
+            thread::sleep(RETRY);
+        });
+        Self { rx }
+    }
+
+    /// Takes the newest reading that arrived since the last call, or
+    /// records why there is none.
+    pub fn update(&self, system_info: &mut SystemInfo) {
+        for received in self.rx.try_iter() {
+            match received {
+                Ok(latest) => *system_info = latest,
+                Err(e) => {
+                    if system_info.errors.get(&Source::Connection) != Some(&e) {
+                        error!("{}", e);
+                    }
+                    system_info.errors.insert(Source::Connection, e);
+                }
+            }
+        }
+    }
+}
+
+/// Reads frames until the connection fails, or the dashboard goes away.
+fn stream_from(
+    address: &str,
+    tls: Option<Arc<ClientConfig>>,
+    token: Option<&str>,
+    tx: &Sender<Result<SystemInfo, CollectorError>>,
+) -> io::Result<()> {
+    let addr = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address did not resolve"))?;
+    let tcp = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+    tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+    let socket = tcp.try_clone()?;
+    let mut stream: Box<dyn Stream> = match tls {
+        Some(config) => {
+            let host = address
+                .rsplit_once(':')
+                .map_or(address, |(host, _)| host)
+                .trim_start_matches('[')
+                .trim_end_matches(']');
+            let name =
+                ServerName::try_from(host.to_string()).map_err(|e| invalid(e.to_string()))?;
+            Box::new(StreamOwned::new(
+                ClientConnection::new(config, name).map_err(io::Error::other)?,
+                tcp,
+            ))
+        }
+        None => Box::new(tcp),
+    };
+    handshake(&mut stream, token)?;
+    // Readings only arrive once per refresh interval, however long that
+    // is, but the agent sends keepalives in between.
+    socket.set_read_timeout(Some(KEEPALIVE * 3))?;
+    info!("Connected to agent {}", address);
+
+    loop {
+        let json = read_frame(&mut stream, MAX_FRAME)?;
+        if json.is_empty() {
+            continue;
+        }
+        let (_, system_info) =
+            session::decode(&json).map_err(|e| invalid(format!("bad snapshot: {}", e)))?;
+        if tx.send(Ok(system_info)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Checks the peer is an agent speaking our version of the protocol and
+/// that it admits us.
+fn handshake<S: Read + Write + ?Sized>(stream: &mut S, token: Option<&str>) -> io::Result<()> {
+    let mut magic = [0; 7];
+    stream.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a sys-dashboard agent".to_string()));
+    }
+    let mut version = [0; 2];
+    stream.read_exact(&mut version)?;
+    let version = u16::from_be_bytes(version);
+    if version != PROTOCOL_VERSION {
+        return Err(invalid(format!(
+            "agent speaks protocol v{}, this dashboard v{}",
+            version, PROTOCOL_VERSION
+        )));
+    }
+
+    let token = token.unwrap_or_default().as_bytes();
+    stream.write_all(&(token.len() as u32).to_be_bytes())?;
+    stream.write_all(token)?;
+    stream.flush()?;
+    let mut answer = [0; 1];
+    stream.read_exact(&mut answer)?;
+    match answer[0] {
+        ACCEPTED => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the agent rejected the token",
+        )),
+    }
+}
+
+/// Puts the TLS failures people run into in terms of the config.
+fn explain(e: &io::Error) -> String {
+    let tls = e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>());
+    match tls {
+        Some(rustls::Error::InvalidCertificate(
+            CertificateError::ApplicationVerificationFailure,
+        )) => "the agent's certificate does not match server_cert".to_string(),
+        Some(rustls::Error::AlertReceived(AlertDescription::CertificateRequired)) => {
+            "the agent requires a client certificate, set cert and key".to_string()
+        }
+        Some(rustls::Error::AlertReceived(
+            AlertDescription::BadCertificate
+            | AlertDescription::CertificateUnknown
+            | AlertDescription::UnknownCA,
+        )) => "the agent does not accept our client certificate".to_string(),
+        _ if matches!(
+            e.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ) =>
+        {
+            "the agent did not answer, it may expect TLS".to_string()
+        }
+        _ if e.kind() == io::ErrorKind::UnexpectedEof => {
+            "the agent closed the connection, it may expect TLS or a client certificate".to_string()
+        }
+        _ => e.to_string(),
+    }
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tls_fixture(name: &str) -> Option<PathBuf> {
+        Some(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("fixtures/tls")
+                .join(name),
+        )
+    }
+
+    /// An agent on a free localhost port.
+    fn agent(settings: &AgentSettings) -> (Dashboards, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let tls = server_tls(settings).unwrap();
+        (
+            Dashboards::accept(listener, tls, settings.token.clone()),
+            address,
+        )
+    }
+
+    fn tls_agent(client_ca: bool, token: Option<&str>) -> (Dashboards, String) {
+        agent(&AgentSettings {
+            cert: tls_fixture("agent.pem"),
+            key: tls_fixture("agent.key"),
+            client_ca: if client_ca {
+                tls_fixture("dashboard.pem")
+            } else {
+                None
+            },
+            token: token.map(String::from),
+        })
+    }
+
+    fn remote(address: &str, server_cert: &str, cert: bool, token: Option<&str>) -> Remote {
+        let (cert, key) = if cert {
+            (tls_fixture("dashboard.pem"), tls_fixture("dashboard.key"))
+        } else {
+            (None, None)
+        };
+        Remote::connect(
+            address,
+            Some(RemoteSettings {
+                address: address.to_string(),
+                server_cert: tls_fixture(server_cert),
+                cert,
+                key,
+                token: token.map(String::from),
+            }),
+        )
+    }
+
+    /// Keeps sending a reading until the dashboard gets it or fails to
+    /// connect.
+    fn receive(dashboards: &Dashboards, remote: &Remote) -> Result<SystemInfo, String> {
+        let mut sample = SystemInfo::default();
+        sample.host = "agent".to_string();
+        for _ in 0..100 {
+            dashboards.send(frame(&sample));
+            match remote.rx.recv_timeout(Duration::from_millis(50)) {
+                Ok(received) => return received.map_err(|e| e.to_string()),
+                Err(_) => continue,
+            }
+        }
+        panic!("the dashboard never connected");
+    }
+
+    #[test]
+    fn pinned_certificate_connects() {
+        let (dashboards, address) = tls_agent(false, None);
+        let remote = remote(&address, "agent.pem", false, None);
+        assert_eq!(receive(&dashboards, &remote).unwrap().host, "agent");
+    }
+
+    #[test]
+    fn other_certificate_is_refused() {
+        let (dashboards, address) = tls_agent(false, None);
+        let remote = remote(&address, "other.pem", false, None);
+        let e = receive(&dashboards, &remote).err().unwrap();
+        assert!(
+            e.ends_with("the agent's certificate does not match server_cert"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn right_token_connects() {
+        let (dashboards, address) = tls_agent(false, Some("secret"));
+        let remote = remote(&address, "agent.pem", false, Some("secret"));
+        assert!(receive(&dashboards, &remote).is_ok());
+    }
+
+    #[test]
+    fn wrong_token_is_rejected() {
+        let (dashboards, address) = tls_agent(false, Some("secret"));
+        let remote = remote(&address, "agent.pem", false, Some("guess"));
+        let e = receive(&dashboards, &remote).err().unwrap();
+        assert!(e.ends_with("the agent rejected the token"), "{}", e);
+    }
+
+    #[test]
+    fn client_certificate_is_admitted() {
+        let (dashboards, address) = tls_agent(true, None);
+        let remote = remote(&address, "agent.pem", true, None);
+        assert!(receive(&dashboards, &remote).is_ok());
+    }
+
+    #[test]
+    fn missing_client_certificate_is_rejected() {
+        let (dashboards, address) = tls_agent(true, None);
+        let remote = remote(&address, "agent.pem", false, None);
+        let e = receive(&dashboards, &remote).err().unwrap();
+        assert!(
+            e.ends_with("the agent requires a client certificate, set cert and key"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn plain_agent_streams_readings() {
+        let (dashboards, address) = agent(&AgentSettings::default());
+        let remote = Remote::connect(&address, None);
+        assert_eq!(receive(&dashboards, &remote).unwrap().host, "agent");
+    }
+
+    /// Accepts one dashboard and plays the agent's side of the handshake
+    /// announcing `version`, then sends `frames`.
+    fn fake_agent(version: u16, frames: Vec<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(MAGIC).unwrap();
+            stream.write_all(&version.to_be_bytes()).unwrap();
+            if read_frame(&mut stream, MAX_TOKEN).is_err() {
+                return;
+            }
+            stream.write_all(&[ACCEPTED]).unwrap();
+            for frame in frames {
+                stream.write_all(&frame).unwrap();
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn other_protocol_version_is_refused() {
+        let address = fake_agent(PROTOCOL_VERSION + 1, vec![]);
+        let (tx, _rx) = channel();
+        let e = stream_from(&address, None, None, &tx).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "agent speaks protocol v{}, this dashboard v{}",
+                PROTOCOL_VERSION + 1,
+                PROTOCOL_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn keepalives_are_skipped() {
+        let mut sample = SystemInfo::default();
+        sample.host = "agent".to_string();
+        let address = fake_agent(PROTOCOL_VERSION, vec![vec![0; 4], frame(&sample)]);
+        let (tx, rx) = channel();
+        thread::spawn(move || stream_from(&address, None, None, &tx));
+        let received = rx.recv_timeout(IO_TIMEOUT).unwrap().ok().unwrap();
+        assert_eq!(received.host, "agent");
+    }
+}
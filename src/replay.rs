@@ -0,0 +1,70 @@
+use crate::session::Player;
+use chrono::{Local, TimeZone};
+use gtk::prelude::*;
+use gtk::{Orientation, Widget};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Play/pause, a seek slider and the time of the sample on show.
+pub struct ReplayBar {
+    container: gtk::Box,
+    toggle: gtk::Button,
+    seek: gtk::Scale,
+    time: gtk::Label,
+}
+
+impl ReplayBar {
+    pub(crate) fn new(player: Rc<RefCell<Player>>) -> Self {
+        let container = gtk::BoxBuilder::new()
+            .orientation(Orientation::Horizontal)
+            .spacing(12)
+            .build();
+        let toggle = gtk::Button::with_label("Pause");
+        // gtk wants a non-empty range even for a single sample.
+        let last = (player.borrow().len() - 1).max(1) as f64;
+        let seek = gtk::Scale::with_range(Orientation::Horizontal, 0., last, 1.);
+        seek.set_draw_value(false);
+        seek.set_hexpand(true);
+        let time = gtk::Label::new(None);
+        time.set_widget_name("replay_time");
+
+        container.pack_start(&toggle, false, false, 0);
+        container.pack_start(&seek, true, true, 0);
+        container.pack_start(&time, false, false, 0);
+
+        let toggled = player.clone();
+        toggle.connect_clicked(move |_| toggled.borrow_mut().toggle());
+        seek.connect_value_changed(move |seek| {
+            // Also fires when `update` moves the slider along, while the
+            // player is borrowed. Only the user's moves need handling.
+            if let Ok(mut player) = player.try_borrow_mut() {
+                player.seek(seek.get_value() as usize);
+            }
+        });
+
+        Self {
+            container,
+            toggle,
+            seek,
+            time,
+        }
+    }
+
+    pub fn update(&self, player: &Player) {
+        self.toggle
+            .set_label(if player.is_playing() { "Pause" } else { "Play" });
+        self.seek.set_value(player.position() as f64);
+        let time = Local.timestamp_millis(player.current_unix_ms());
+        self.time.set_text(&format!(
+            "{}  {}/{}  {}x",
+            time.format("%Y-%m-%d %H:%M:%S"),
+            player.position() + 1,
+            player.len(),
+            player.speed()
+        ));
+    }
+
+    pub(super) fn widget(&self) -> &impl IsA<Widget> {
+        &self.container
+    }
+}
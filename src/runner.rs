@@ -0,0 +1,134 @@
+use crate::error::CollectorError;
+use crate::fmt::trim_newline;
+use std::fs::read_to_string;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Runs the external commands collectors read from (`nvidia-smi`, `lscpu`,
+/// ...) and returns their stdout.
+pub trait CommandRunner {
+    fn run(&self, command: &str, args: &[&str]) -> Result<String, CollectorError>;
+}
+
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, command: &str, args: &[&str]) -> Result<String, CollectorError> {
+        let output = Command::new(command)
+            .args(args)
+            .output()
+            .map_err(|e| CollectorError::CommandFailed(command.to_string(), e.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr
+                .lines()
+                .next()
+                .map(String::from)
+                .unwrap_or_else(|| output.status.to_string());
+            return Err(CollectorError::CommandFailed(command.to_string(), reason));
+        }
+        let mut raw = String::from_utf8_lossy(&output.stdout).into_owned();
+        trim_newline(&mut raw);
+        Ok(raw)
+    }
+}
+
+/// Plays back outputs recorded earlier, one file per command named after
+/// it, e.g. `fixtures/commands/rtx-3090/nvidia-smi`. A recording stands in
+/// for every invocation of its command, except that `--id=N` keeps only the
+/// header and the Nth device's row, as `nvidia-smi` does.
+pub struct RecordedRunner {
+    dir: PathBuf,
+}
+
+impl RecordedRunner {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+}
+
+impl CommandRunner for RecordedRunner {
+    fn run(&self, command: &str, args: &[&str]) -> Result<String, CollectorError> {
+        let path = self.dir.join(command);
+        let mut raw = read_to_string(&path).map_err(|e| {
+            CollectorError::CommandFailed(
+                command.to_string(),
+                format!("no recording at {}: {}", path.display(), e),
+            )
+        })?;
+        trim_newline(&mut raw);
+        match args.iter().find_map(|arg| arg.strip_prefix("--id=")) {
+            Some(id) => select_device(command, &raw, id),
+            None => Ok(raw),
+        }
+    }
+}
+
+/// The header of a recorded device listing and the row of device `id`.
+fn select_device(command: &str, raw: &str, id: &str) -> Result<String, CollectorError> {
+    let mut lines = raw.lines();
+    let header = lines.next().unwrap_or_default();
+    id.parse::<usize>()
+        .ok()
+        .and_then(|index| lines.nth(index))
+        .map(|row| format!("{}\n{}", header, row))
+        .ok_or_else(|| {
+            CollectorError::CommandFailed(
+                command.to_string(),
+                format!("no device {} in the recording", id),
+            )
+        })
+}
+
+/// The runner a `SystemInfo` collects through, the real commands unless
+/// configured otherwise.
+pub struct Runner(Box<dyn CommandRunner + Send>);
+
+impl Runner {
+    pub fn new<R: CommandRunner + Send + 'static>(runner: R) -> Self {
+        Runner(Box::new(runner))
+    }
+
+    pub fn from_recordings(dir: Option<&Path>) -> Self {
+        match dir {
+            Some(dir) => Runner::new(RecordedRunner::new(dir)),
+            None => Runner::new(SystemRunner),
+        }
+    }
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Runner::new(SystemRunner)
+    }
+}
+
+impl Deref for Runner {
+    type Target = dyn CommandRunner + Send;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_selects_one_recorded_device() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/commands/a100-x4");
+        let runner = RecordedRunner::new(&dir);
+        let all = runner.run("nvidia-smi", &[]).unwrap();
+        assert_eq!(all.lines().count(), 5);
+        let second = runner.run("nvidia-smi", &["--id=1"]).unwrap();
+        let lines = second.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], all.lines().next().unwrap());
+        assert!(lines[1].contains(" 58, 97,"), "{}", lines[1]);
+        assert!(runner.run("nvidia-smi", &["--id=4"]).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use cairo::{Context, Format, ImageSurface};
+use gtk::prelude::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Renders the window into an image rather than reading the screen, so it
+/// works while covered, minimised or never shown at all.
+pub fn save(window: &gtk::Widget, path: &Path) -> Result<(), String> {
+    let surface = ImageSurface::create(
+        Format::ARgb32,
+        window.get_allocated_width(),
+        window.get_allocated_height(),
+    )
+    .map_err(|e| format!("cannot create image: {}", e))?;
+    window.draw(&Context::new(&surface));
+    surface.flush();
+
+    let mut file =
+        File::create(path).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    surface
+        .write_to_png(&mut file)
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+pub fn ask(parent: &gtk::ApplicationWindow) -> Option<PathBuf> {
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some("Save screenshot"),
+        Some(parent),
+        gtk::FileChooserAction::Save,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_do_overwrite_confirmation(true);
+    dialog.set_current_name("sys-dashboard.png");
+    let png = gtk::FileFilter::new();
+    png.set_name(Some("PNG images"));
+    png.add_mime_type("image/png");
+    dialog.add_filter(&png);
+
+    let response = dialog.run();
+    let path = dialog.get_filename();
+    dialog.close();
+    match response {
+        gtk::ResponseType::Accept => path,
+        _ => None,
+    }
+}
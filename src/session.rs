@@ -0,0 +1,367 @@
+use crate::snapshot::Snapshot;
+use crate::system::SystemInfo;
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Appends readings to a session file, one JSON snapshot per line, at most
+/// one per interval.
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+    interval: Duration,
+    last_recorded: Option<Instant>,
+    failing: bool,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, interval_ms: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        info!("Recording to {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            interval: Duration::from_millis(interval_ms),
+            last_recorded: None,
+            failing: false,
+        })
+    }
+
+    /// Skipped while the interval since the last recorded reading runs.
+    pub fn record(&mut self, system_info: &SystemInfo) {
+        let now = Instant::now();
+        if self
+            .last_recorded
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return;
+        }
+        self.last_recorded = Some(now);
+        let mut line =
+            serde_json::to_string(&Snapshot::new(system_info)).expect("Snapshots always serialize");
+        line.push('\n');
+        // One write per line, so a crash leaves at most one partial line.
+        match self.file.write_all(line.as_bytes()) {
+            Ok(()) => self.failing = false,
+            Err(e) => {
+                if !self.failing {
+                    error!("Cannot record to {}: {}", self.path.display(), e);
+                    self.failing = true;
+                }
+            }
+        }
+    }
+}
+
+/// The parts of a recorded snapshot replay needs, the derived metrics are
+/// recomputed from `system`.
+#[derive(Deserialize)]
+struct Recorded {
+    unix_ms: i64,
+    system: SystemInfo,
+}
+
+/// Just the time of a recorded snapshot, for indexing a session.
+#[derive(Deserialize)]
+struct Stamp {
+    unix_ms: i64,
+}
+
+/// Parses one serialized `Snapshot` back into its time and readings.
+pub fn decode(json: &[u8]) -> serde_json::Result<(i64, SystemInfo)> {
+    serde_json::from_slice::<Recorded>(json).map(|recorded| (recorded.unix_ms, recorded.system))
+}
+
+/// A session file and where in it each sample starts, oldest first.
+/// Samples are read when asked for rather than held in memory, so long
+/// recordings open quickly.
+pub struct Session {
+    path: PathBuf,
+    file: BufReader<File>,
+    /// The time and byte offset of every sample.
+    index: Vec<(i64, u64)>,
+}
+
+impl Session {
+    /// Indexes the session. Lines that don't parse, such as one cut short
+    /// when the recording was killed, are skipped.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let unreadable = |e: io::Error| format!("cannot read {}: {}", path.display(), e);
+        let mut file = BufReader::new(File::open(path).map_err(unreadable)?);
+        let mut index = vec![];
+        let mut offset = 0;
+        let mut line = String::new();
+        for number in 1.. {
+            line.clear();
+            let read = file.read_line(&mut line).map_err(unreadable)?;
+            if read == 0 {
+                break;
+            }
+            if !line.trim().is_empty() {
+                match serde_json::from_str::<Stamp>(&line) {
+                    Ok(stamp) => index.push((stamp.unix_ms, offset)),
+                    Err(e) => warn!("{}:{}: skipping sample: {}", path.display(), number, e),
+                }
+            }
+            offset += read as u64;
+        }
+        if index.is_empty() {
+            return Err(format!("{} holds no samples", path.display()));
+        }
+        index.sort_by_key(|(unix_ms, _)| *unix_ms);
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            index,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn unix_ms(&self, position: usize) -> i64 {
+        self.index[position].0
+    }
+
+    /// The readings of the sample at `position`.
+    pub fn read(&mut self, position: usize) -> Result<SystemInfo, String> {
+        let offset = self.index[position].1;
+        let mut line = String::new();
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_line(&mut line))
+            .map_err(|e| format!("cannot read {}: {}", self.path.display(), e))?;
+        decode(line.as_bytes())
+            .map(|(_, system_info)| system_info)
+            .map_err(|e| {
+                format!(
+                    "{}: bad sample at byte {}: {}",
+                    self.path.display(),
+                    offset,
+                    e
+                )
+            })
+    }
+
+    /// Hands every sample to `each`, oldest first, skipping any that no
+    /// longer read.
+    pub fn for_each<F>(&mut self, mut each: F)
+    where
+        F: FnMut(i64, &SystemInfo),
+    {
+        for position in 0..self.len() {
+            match self.read(position) {
+                Ok(system_info) => each(self.unix_ms(position), &system_info),
+                Err(e) => warn!("{}", e),
+            }
+        }
+    }
+}
+
+/// Steps through a recorded session at a multiple of real time.
+pub struct Player {
+    session: Session,
+    position: usize,
+    /// The readings at `position`.
+    current: SystemInfo,
+    speed: f64,
+    playing: bool,
+    /// Milliseconds of the session played back, from its first sample.
+    elapsed_ms: f64,
+    last_advance: Option<Instant>,
+}
+
+impl Player {
+    pub fn load(path: &Path, speed: f64) -> Result<Self, String> {
+        let mut session = Session::open(path)?;
+        let current = session.read(0)?;
+        info!(
+            "Replaying {} samples from {}",
+            session.len(),
+            path.display()
+        );
+        Ok(Self {
+            session,
+            position: 0,
+            current,
+            speed,
+            playing: true,
+            elapsed_ms: 0.,
+            last_advance: None,
+        })
+    }
+
+    /// Moves forward by the time passed since the last call, scaled by the
+    /// speed, and stops at the end of the session.
+    pub fn advance(&mut self) {
+        let now = Instant::now();
+        if self.playing {
+            if let Some(last) = self.last_advance {
+                self.elapsed_ms += now.duration_since(last).as_secs_f64() * 1000. * self.speed;
+            }
+            let start = self.session.unix_ms(0);
+            let mut position = self.position;
+            while position + 1 < self.len()
+                && (self.session.unix_ms(position + 1) - start) as f64 <= self.elapsed_ms
+            {
+                position += 1;
+            }
+            self.show(position);
+            if self.position + 1 == self.len() {
+                self.playing = false;
+            }
+        }
+        self.last_advance = Some(now);
+    }
+
+    pub fn seek(&mut self, position: usize) {
+        self.show(position.min(self.len() - 1));
+        self.elapsed_ms = (self.session.unix_ms(self.position) - self.session.unix_ms(0)) as f64;
+    }
+
+    /// Reads the sample at `position`. One that no longer reads is stepped
+    /// over, keeping the readings before it.
+    fn show(&mut self, position: usize) {
+        if position == self.position {
+            return;
+        }
+        self.position = position;
+        match self.session.read(position) {
+            Ok(system_info) => self.current = system_info,
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    /// Pauses, or resumes, starting over if the end was reached.
+    pub fn toggle(&mut self) {
+        if !self.playing && self.position + 1 == self.len() {
+            self.seek(0);
+        }
+        self.playing = !self.playing;
+    }
+
+    /// Hands every sample of the session to `each`, oldest first.
+    pub fn for_each_sample<F>(&mut self, each: F)
+    where
+        F: FnMut(i64, &SystemInfo),
+    {
+        self.session.for_each(each);
+    }
+
+    pub fn current(&self) -> &SystemInfo {
+        &self.current
+    }
+
+    pub fn current_unix_ms(&self) -> i64 {
+        self.session.unix_ms(self.position)
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.session.len()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("sys-dashboard-{}-{}", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// A recorded line taken at `unix_ms` with `cpu_usage`.
+    fn line(unix_ms: i64, cpu_usage: u64) -> String {
+        let mut system_info = SystemInfo::default();
+        system_info.cpu_usage = cpu_usage;
+        let mut snapshot = serde_json::to_value(Snapshot::new(&system_info)).unwrap();
+        snapshot["unix_ms"] = unix_ms.into();
+        snapshot.to_string() + "\n"
+    }
+
+    #[test]
+    fn records_at_most_once_per_interval() {
+        let path = temp_file("session-interval");
+        let mut recorder = Recorder::create(&path, 60_000).unwrap();
+        recorder.record(&SystemInfo::default());
+        recorder.record(&SystemInfo::default());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+
+        let mut recorder = Recorder::create(&path, 0).unwrap();
+        recorder.record(&SystemInfo::default());
+        recorder.record(&SystemInfo::default());
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn indexes_in_time_order_and_skips_what_doesnt_parse() {
+        let path = temp_file("session-index");
+        let contents = line(3000, 30) + &line(1000, 10) + "\n" + &line(2000, 20);
+        // Cut short when the recording was killed.
+        fs::write(&path, contents.clone() + &line(4000, 40)[..20]).unwrap();
+
+        let mut session = Session::open(&path).unwrap();
+        assert_eq!(session.len(), 3);
+        let times = (0..3).map(|i| session.unix_ms(i)).collect::<Vec<_>>();
+        assert_eq!(times, [1000, 2000, 3000]);
+        assert_eq!(session.read(2).unwrap().cpu_usage, 30);
+        assert_eq!(session.read(0).unwrap().cpu_usage, 10);
+
+        let mut usage = vec![];
+        session.for_each(|unix_ms, system_info| usage.push((unix_ms, system_info.cpu_usage)));
+        assert_eq!(usage, [(1000, 10), (2000, 20), (3000, 30)]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn an_empty_session_is_an_error() {
+        let path = temp_file("session-empty");
+        fs::write(&path, "\n").unwrap();
+        assert!(Session::open(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn player_seeks_and_starts_over_at_the_end() {
+        let path = temp_file("session-player");
+        fs::write(&path, line(1000, 10) + &line(2000, 20) + &line(3000, 30)).unwrap();
+        let mut player = Player::load(&path, 1.).unwrap();
+        assert_eq!(player.current().cpu_usage, 10);
+
+        player.seek(1);
+        assert_eq!(
+            (player.current_unix_ms(), player.current().cpu_usage),
+            (2000, 20)
+        );
+        player.seek(10);
+        assert_eq!(player.position(), 2);
+        player.advance();
+        assert!(!player.is_playing());
+
+        player.toggle();
+        assert!(player.is_playing());
+        assert_eq!((player.position(), player.current().cpu_usage), (0, 10));
+        let _ = fs::remove_file(&path);
+    }
+}
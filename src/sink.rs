@@ -0,0 +1,626 @@
+use crate::metric;
+use crate::system::SystemInfo;
+use chrono::Utc;
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest UDP write, so a batch fits in one Ethernet frame and isn't
+/// fragmented or dropped on the way.
+const MAX_DATAGRAM: usize = 1400;
+
+/// Somewhere to push metrics to. Anything listening on the target works
+/// for trying one out, e.g. `nc -lk 2003` for Graphite over TCP or
+/// `nc -lku 8089` for Influx over UDP.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SinkConfig {
+    pub format: SinkFormat,
+    pub transport: Transport,
+    /// `host:port`, or for HTTP the write URL, e.g.
+    /// `http://localhost:8086/write?db=sysdash`.
+    pub target: String,
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    /// Most points sent in one write. UDP writes are also kept to what
+    /// fits in one packet.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Most points kept while the target is unreachable. The oldest are
+    /// dropped beyond that.
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// Influx measurement prefix, or the first Graphite path component.
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// Sent as `Authorization: Token ...` with HTTP writes.
+    pub token: Option<String>,
+}
+
+fn default_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_buffer_size() -> usize {
+    10_000
+}
+
+fn default_prefix() -> String {
+    "sysdash".to_string()
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkFormat {
+    Influx,
+    Graphite,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+    Http,
+}
+
+impl SinkConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.format == SinkFormat::Graphite && self.transport != Transport::Tcp {
+            return Err(format!(
+                "sink '{}': graphite is only supported over tcp",
+                self.target
+            ));
+        }
+        if self.transport == Transport::Http && parse_url(&self.target).is_none() {
+            return Err(format!(
+                "sink '{}': expected a URL like http://host:port/path",
+                self.target
+            ));
+        }
+        if self.interval_ms == 0 || self.batch_size == 0 || self.buffer_size == 0 {
+            return Err(format!(
+                "sink '{}': interval_ms, batch_size and buffer_size must be at least 1",
+                self.target
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SinkConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} sink {}", self.format, self.target)
+    }
+}
+
+/// Formats readings for each configured sink on its interval and hands
+/// them to a writer thread per sink, so a slow or dead target never holds
+/// up the refresh.
+pub struct Sinks {
+    sinks: Vec<Sink>,
+}
+
+struct Sink {
+    config: SinkConfig,
+    tx: Sender<Vec<String>>,
+    last_push: Option<Instant>,
+    /// What the writer hadn't sent when it stopped.
+    backlog: Receiver<Backlog>,
+}
+
+/// Points a writer hadn't sent when its sink was reconfigured, handed to
+/// the writer replacing it.
+struct Backlog {
+    points: VecDeque<String>,
+    dropped: u64,
+}
+
+impl Sinks {
+    pub fn new(configs: Vec<SinkConfig>) -> Self {
+        Self {
+            sinks: configs
+                .into_iter()
+                .map(|config| Sink::start(config, None))
+                .collect(),
+        }
+    }
+
+    /// Replaces the sinks after a config reload. A sink writing to the same
+    /// target in the same way keeps the points it hadn't sent yet.
+    pub fn reconfigure(&mut self, configs: Vec<SinkConfig>) {
+        let mut old = std::mem::take(&mut self.sinks);
+        self.sinks = configs
+            .into_iter()
+            .map(|config| {
+                let same = old.iter().position(|sink| {
+                    sink.config.target == config.target
+                        && sink.config.format == config.format
+                        && sink.config.transport == config.transport
+                });
+                // Dropping the old sink stops its writer, which then hands
+                // its backlog over.
+                let backlog = same.map(|index| old.remove(index).backlog);
+                Sink::start(config, backlog)
+            })
+            .collect();
+    }
+
+    pub fn publish(&mut self, system_info: &SystemInfo) {
+        for sink in &mut self.sinks {
+            let interval = Duration::from_millis(sink.config.interval_ms);
+            if sink.last_push.is_some_and(|last| last.elapsed() < interval) {
+                continue;
+            }
+            sink.last_push = Some(Instant::now());
+            let points = points(&sink.config, system_info);
+            // The writer only goes away with its sender, so this can't fail.
+            let _ = sink.tx.send(points);
+        }
+    }
+}
+
+impl Sink {
+    fn start(config: SinkConfig, inherited: Option<Receiver<Backlog>>) -> Self {
+        let (tx, rx) = channel();
+        let (handoff, backlog) = channel();
+        let writer = Writer::new(config.clone());
+        thread::spawn(move || writer.run(rx, inherited, handoff));
+        Self {
+            config,
+            tx,
+            last_push: None,
+            backlog,
+        }
+    }
+}
+
+fn points(config: &SinkConfig, system_info: &SystemInfo) -> Vec<String> {
+    let now = Utc::now();
+    let metrics = metric::collect(system_info);
+    match config.format {
+        SinkFormat::Influx => {
+            // cpu.freq.avg becomes field freq_avg of measurement
+            // sysdash_cpu, one line per measurement.
+            let mut lines: Vec<(String, Vec<String>)> = vec![];
+            for m in &metrics {
+                let value = match m.value {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let (group, field) = m.name.split_once('.').unwrap_or(("system", m.name));
+                let measurement = format!("{}_{}", config.prefix, group);
+                let field = format!("{}={}", field.replace('.', "_"), value);
+                match lines.iter_mut().find(|(name, _)| *name == measurement) {
+                    Some((_, fields)) => fields.push(field),
+                    None => lines.push((measurement, vec![field])),
+                }
+            }
+            lines
+                .into_iter()
+                .map(|(measurement, fields)| {
+                    format!(
+                        "{},host={} {} {}",
+                        escape_influx(&measurement),
+                        escape_influx(&system_info.host),
+                        fields.join(","),
+                        now.timestamp_nanos()
+                    )
+                })
+                .collect()
+        }
+        SinkFormat::Graphite => {
+            let host = system_info.host.replace('.', "_");
+            metrics
+                .iter()
+                .filter_map(|m| {
+                    m.value.map(|value| {
+                        format!(
+                            "{}.{}.{} {} {}",
+                            config.prefix,
+                            host,
+                            m.name,
+                            value,
+                            now.timestamp()
+                        )
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+/// Commas, spaces and equals signs delimit line protocol, so escape them in
+/// names and tag values.
+fn escape_influx(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+}
+
+/// Owns the connection and the backlog of one sink.
+struct Writer {
+    config: SinkConfig,
+    buffer: VecDeque<String>,
+    dropped: u64,
+    stream: Option<TcpStream>,
+    failing: bool,
+}
+
+impl Writer {
+    fn new(config: SinkConfig) -> Self {
+        Self {
+            config,
+            buffer: VecDeque::new(),
+            dropped: 0,
+            stream: None,
+            failing: false,
+        }
+    }
+
+    fn run(
+        mut self,
+        rx: Receiver<Vec<String>>,
+        inherited: Option<Receiver<Backlog>>,
+        handoff: Sender<Backlog>,
+    ) {
+        if let Some(Ok(backlog)) = inherited.map(|backlog| backlog.recv()) {
+            self.buffer = backlog.points;
+            self.dropped = backlog.dropped;
+        }
+        for points in rx {
+            self.push(points);
+            self.flush();
+        }
+        // The sink was reconfigured or removed, its replacement, if any,
+        // sends the rest.
+        let _ = handoff.send(Backlog {
+            points: self.buffer,
+            dropped: self.dropped,
+        });
+    }
+
+    fn push(&mut self, points: Vec<String>) {
+        self.buffer.extend(points);
+        let excess = self.buffer.len().saturating_sub(self.config.buffer_size);
+        if excess > 0 {
+            self.buffer.drain(..excess);
+            self.dropped += excess as u64;
+            warn!(
+                "{}: buffer full, dropped {} points ({} so far)",
+                self.config, excess, self.dropped
+            );
+        }
+    }
+
+    /// Sends everything buffered, a batch at a time, stopping at the first
+    /// failure so the rest is retried with the next reading.
+    fn flush(&mut self) {
+        while !self.buffer.is_empty() {
+            let mut batch = String::new();
+            if self.dropped > 0 {
+                batch.push_str(&self.dropped_point());
+            }
+            let mut len = 0;
+            for point in self.buffer.iter().take(self.config.batch_size) {
+                // A point too large for a datagram on its own still goes out
+                // alone, the network may cope.
+                let fits = batch.len() + point.len() < MAX_DATAGRAM;
+                if self.config.transport == Transport::Udp && len > 0 && !fits {
+                    break;
+                }
+                batch.push_str(point);
+                batch.push('\n');
+                len += 1;
+            }
+            match self.send(&batch) {
+                Ok(()) => {
+                    self.buffer.drain(..len);
+                    if self.failing {
+                        self.failing = false;
+                        info!("{} is reachable again", self.config);
+                    }
+                }
+                Err(SendError::Rejected(reason)) => {
+                    // Retrying data the server refuses would wedge the sink.
+                    self.buffer.drain(..len);
+                    self.dropped += len as u64;
+                    warn!("{} rejected {} points: {}", self.config, len, reason);
+                }
+                Err(SendError::Io(e)) => {
+                    self.stream = None;
+                    if !self.failing {
+                        self.failing = true;
+                        warn!("{} unreachable, buffering: {}", self.config, e);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Reports the dropped points through the sink itself.
+    fn dropped_point(&self) -> String {
+        match self.config.format {
+            SinkFormat::Influx => format!(
+                "{}_sink dropped_points={}i {}\n",
+                escape_influx(&self.config.prefix),
+                self.dropped,
+                Utc::now().timestamp_nanos()
+            ),
+            SinkFormat::Graphite => format!(
+                "{}.sink.dropped_points {} {}\n",
+                self.config.prefix,
+                self.dropped,
+                Utc::now().timestamp()
+            ),
+        }
+    }
+
+    fn send(&mut self, batch: &str) -> Result<(), SendError> {
+        match self.config.transport {
+            Transport::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.send_to(batch.as_bytes(), resolve(&self.config.target)?)?;
+                Ok(())
+            }
+            Transport::Tcp => {
+                if self.stream.is_none() {
+                    self.stream = Some(connect(&self.config.target)?);
+                }
+                let stream = self.stream.as_mut().expect("connected above");
+                stream.write_all(batch.as_bytes())?;
+                Ok(())
+            }
+            Transport::Http => {
+                let (host, path) = parse_url(&self.config.target).expect("validated on load");
+                post(host, path, self.config.token.as_deref(), batch)
+            }
+        }
+    }
+}
+
+enum SendError {
+    Io(io::Error),
+    Rejected(String),
+}
+
+impl From<io::Error> for SendError {
+    fn from(e: io::Error) -> Self {
+        SendError::Io(e)
+    }
+}
+
+fn resolve(target: &str) -> io::Result<std::net::SocketAddr> {
+    target.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} did not resolve", target),
+        )
+    })
+}
+
+fn connect(target: &str) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&resolve(target)?, IO_TIMEOUT)?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// `http://host:port/path?query` split into `host:port` and the rest.
+fn parse_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("http://")?;
+    let split = rest.find('/').unwrap_or(rest.len());
+    let (host, path) = rest.split_at(split);
+    if host.is_empty() {
+        None
+    } else if path.is_empty() {
+        Some((host, "/"))
+    } else {
+        Some((host, path))
+    }
+}
+
+fn post(host: &str, path: &str, token: Option<&str>, body: &str) -> Result<(), SendError> {
+    let target = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let mut stream = connect(&target)?;
+    let auth = token
+        .map(|token| format!("Authorization: Token {}\r\n", token))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\n{}Content-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        auth,
+        body.len(),
+        body
+    )?;
+
+    let mut status_line = String::new();
+    BufReader::new(&stream).read_line(&mut status_line)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .unwrap_or(0);
+    match status {
+        200..=299 => Ok(()),
+        // Too many requests and server errors are worth retrying.
+        429 | 500..=599 => Err(SendError::Io(io::Error::other(
+            status_line.trim().to_string(),
+        ))),
+        _ => Err(SendError::Rejected(status_line.trim().to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn sink(format: SinkFormat, transport: Transport, target: String) -> SinkConfig {
+        SinkConfig {
+            format,
+            transport,
+            target,
+            interval_ms: 1,
+            batch_size: default_batch_size(),
+            buffer_size: default_buffer_size(),
+            prefix: default_prefix(),
+            token: None,
+        }
+    }
+
+    fn reading() -> SystemInfo {
+        let mut system_info = SystemInfo::default();
+        system_info.host = "box".to_string();
+        system_info.cpu_usage = 42;
+        system_info
+    }
+
+    /// A port nothing listens on, until it's bound again.
+    fn closed_port() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    /// Everything written to `listener` until the writer stops for a while.
+    fn received(listener: &TcpListener) -> String {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let mut text = String::new();
+        let mut chunk = [0; 4096];
+        while let Ok(n @ 1..) = stream.read(&mut chunk) {
+            text.push_str(std::str::from_utf8(&chunk[..n]).unwrap());
+        }
+        text
+    }
+
+    #[test]
+    fn graphite_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let mut sinks = Sinks::new(vec![sink(SinkFormat::Graphite, Transport::Tcp, target)]);
+        sinks.publish(&reading());
+        let text = received(&listener);
+        let usage = text
+            .lines()
+            .find(|line| line.starts_with("sysdash.box.cpu.usage "))
+            .unwrap();
+        let fields = usage.split(' ').collect::<Vec<_>>();
+        assert_eq!(fields[1], "42");
+        assert!(fields[2].parse::<i64>().unwrap() > 1_600_000_000);
+        assert!(text.contains("sysdash.box.mem.total 0 "));
+    }
+
+    #[test]
+    fn influx_over_udp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let target = socket.local_addr().unwrap().to_string();
+        let mut sinks = Sinks::new(vec![sink(SinkFormat::Influx, Transport::Udp, target)]);
+        sinks.publish(&reading());
+        let mut datagram = [0; MAX_DATAGRAM];
+        let n = socket.recv(&mut datagram).unwrap();
+        let text = std::str::from_utf8(&datagram[..n]).unwrap();
+        let cpu = text
+            .lines()
+            .find(|line| line.starts_with("sysdash_cpu,"))
+            .unwrap();
+        let (fields, timestamp) = cpu.rsplit_once(' ').unwrap();
+        assert_eq!(
+            fields,
+            "sysdash_cpu,host=box usage=42,temperature=0,freq_avg=0,freq_max=0"
+        );
+        assert!(timestamp.parse::<i64>().unwrap() > 1_600_000_000_000_000_000);
+        assert!(text.contains("sysdash_mem,host=box used=0,total=0 "));
+    }
+
+    #[test]
+    fn buffers_while_the_target_is_down() {
+        let target = closed_port();
+        let mut config = sink(SinkFormat::Graphite, Transport::Tcp, target.clone());
+        config.buffer_size = 3;
+        let mut writer = Writer::new(config);
+        writer.push((0..5).map(|i| format!("sysdash.point {} 1", i)).collect());
+        writer.flush();
+        assert_eq!(writer.buffer.len(), 3);
+        assert_eq!(writer.dropped, 2);
+        assert!(writer.failing);
+
+        let listener = TcpListener::bind(&target).unwrap();
+        writer.flush();
+        assert!(writer.buffer.is_empty());
+        let text = received(&listener);
+        assert!(
+            text.starts_with("sysdash.sink.dropped_points 2 "),
+            "{}",
+            text
+        );
+        assert!(text.ends_with("sysdash.point 2 1\nsysdash.point 3 1\nsysdash.point 4 1\n"));
+    }
+
+    #[test]
+    fn udp_batches_fit_in_a_packet() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let target = socket.local_addr().unwrap().to_string();
+        let mut writer = Writer::new(sink(SinkFormat::Influx, Transport::Udp, target));
+        let point = format!("sysdash_test value={}", "9".repeat(80));
+        writer.push(vec![point.clone(); 100]);
+        writer.flush();
+
+        let mut lines = 0;
+        let mut datagram = [0; 65536];
+        while lines < 100 {
+            let n = socket.recv(&mut datagram).unwrap();
+            assert!(n <= MAX_DATAGRAM, "{} bytes", n);
+            lines += std::str::from_utf8(&datagram[..n]).unwrap().lines().count();
+        }
+        assert_eq!(lines, 100);
+    }
+
+    #[test]
+    fn reload_keeps_unsent_points() {
+        let target = closed_port();
+        let config = sink(SinkFormat::Graphite, Transport::Tcp, target.clone());
+        let mut sinks = Sinks::new(vec![config.clone()]);
+        sinks.publish(&reading());
+        // Give the writer time to fail.
+        thread::sleep(Duration::from_millis(200));
+        sinks.reconfigure(vec![config]);
+
+        let listener = TcpListener::bind(&target).unwrap();
+        sinks.publish(&reading());
+        let text = received(&listener);
+        let usage = text
+            .lines()
+            .filter(|line| line.starts_with("sysdash.box.cpu.usage "))
+            .count();
+        assert_eq!(usage, 2, "{}", text);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::config::{Config, Overrides};
+use crate::metric::{self, Metric};
+use crate::system::SystemInfo;
+use chrono::Local;
+use log::error;
+use serde::Serialize;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Every metric at one point in time, along with the raw readings they were
+/// derived from.
+#[derive(Serialize)]
+pub struct Snapshot<'a> {
+    /// RFC 3339, local time.
+    pub timestamp: String,
+    pub unix_ms: i64,
+    pub metrics: Vec<Metric>,
+    pub system: &'a SystemInfo,
+}
+
+impl<'a> Snapshot<'a> {
+    pub fn new(system_info: &'a SystemInfo) -> Self {
+        let now = Local::now();
+        Self {
+            timestamp: now.to_rfc3339(),
+            unix_ms: now.timestamp_millis(),
+            metrics: metric::collect(system_info),
+            system: system_info,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+/// Runs every collector once and prints the result. CPU usage is a delta
+/// between two readings, so this takes one refresh interval.
+pub fn run(config_path: &Path, overrides: &Overrides, format: Format) {
+    let config = Config::load(config_path, overrides).unwrap_or_else(|e| {
+        error!("Using default configuration: {}", e);
+        Config::default()
+    });
+    let mut system_info = SystemInfo::new(&config);
+    system_info.update();
+    thread::sleep(Duration::from_millis(config.refresh.interval_ms));
+    system_info.update();
+
+    let snapshot = Snapshot::new(&system_info);
+    match format {
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&snapshot).expect("Snapshots always serialize")
+        ),
+    }
+}
@@ -19,6 +19,22 @@ window {
  font-size: 80px
 }
 
+#status {
+ font-size: 14px;
+ color: #ff5555;
+}
+
+.tile {
+ border: 1px solid #444444;
+}
+.tile.firing {
+ border-color: #ff5555;
+}
+
+#tile_host {
+ font-size: 24px;
+}
+
 #cpu_usage{
  font-size: 40px
 }
@@ -1,98 +1,294 @@
-use crate::fmt::trim_newline;
+use crate::config::{Config, Sensors};
+use crate::energy::{EnergyMeter, EnergyReading, EnergySettings};
+use crate::error::{CollectorError, Source};
+use crate::paths::HostPaths;
+use crate::runner::{CommandRunner, Runner};
+use crate::units::Bytes;
 use csv::{Reader, ReaderBuilder};
+use float_ord::FloatOrd;
+use log::{info, warn};
 use regex::Regex;
-use serde::Deserialize;
-use std::error::Error;
-use std::fs::read_to_string;
-use std::process::Command;
-use toml::Value;
-
-#[derive(Default)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fs::{self, read_to_string};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct SystemInfo {
     pub(crate) user: String,
     pub(crate) host: String,
     pub os: String,
     pub datetime: String,
     pub cpu_usage: u64,
+    /// Usage of each core, in /proc/stat order.
+    pub core_usage: Vec<u64>,
     pub cpu_temp: u8,
     pub cpu_name: String,
+    /// The card at `sensors.gpu_index`, which the GPU panel and the `gpu.*`
+    /// metrics show.
     pub gpu_info: GPUInfo,
+    /// Every card, in `nvidia-smi` order.
+    #[serde(default)]
+    pub gpus: Vec<GPUInfo>,
+    #[serde(skip)]
     pub cpu_usage_info: CPUUsageInfo,
     pub memory_info: MemInfo,
+    /// Frequency of each core in MHz, in /proc/cpuinfo order.
     pub cpu_freq: Vec<f32>,
+    /// CPU package power in watts, from RAPL.
+    pub cpu_power: Option<f32>,
+    #[serde(default)]
+    pub energy: EnergyReading,
+    #[serde(
+        serialize_with = "serialize_errors",
+        deserialize_with = "deserialize_errors"
+    )]
+    pub errors: BTreeMap<Source, CollectorError>,
+    #[serde(skip)]
+    sensors: Sensors,
+    #[serde(skip)]
+    paths: HostPaths,
+    #[serde(skip)]
+    runner: Runner,
+    #[serde(skip)]
+    gpu_interval: Duration,
+    #[serde(skip)]
+    last_gpu_poll: Option<Instant>,
+    #[serde(skip)]
+    last_rapl: Option<RaplSample>,
+    #[serde(skip)]
+    energy_meter: Option<EnergyMeter>,
+    #[serde(skip)]
+    cost_per_kwh: Option<f64>,
+}
+
+fn serialize_errors<S: Serializer>(
+    errors: &BTreeMap<Source, CollectorError>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(errors.iter().map(|(source, e)| (source, e.to_string())))
+}
+
+/// Only the message survives a round trip, which is all the views show.
+fn deserialize_errors<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<Source, CollectorError>, D::Error> {
+    let errors = BTreeMap::<Source, String>::deserialize(deserializer)?;
+    Ok(errors
+        .into_iter()
+        .map(|(source, e)| (source, CollectorError::Malformed(e)))
+        .collect())
 }
 
 impl SystemInfo {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         let mut system_info = Self::default();
+        system_info.configure(config);
 
-        if let Ok(gpu_info) = get_gpu_info() {
-            system_info.gpu_info = gpu_info;
-        }
+        system_info.poll_gpus();
+        let runner = &*system_info.runner;
+        let cpu_name = get_cpu_name(runner);
+        let user = get_user(runner);
+        let host = get_host(runner);
 
-        if let Some(cpu_name) = get_cpu_name() {
+        if let Some(cpu_name) = system_info.record(Source::CpuName, cpu_name) {
             system_info.cpu_name = cpu_name;
         }
-        if let Some(user) = get_user() {
+        if let Some(user) = system_info.record(Source::User, user) {
             system_info.user = user
         }
-        if let Some(host) = get_host() {
+        if let Some(host) = system_info.record(Source::Host, host) {
             system_info.host = host
         }
-        if let Some(os) = get_os() {
+        let os = get_os(&system_info.paths);
+        if let Some(os) = system_info.record(Source::Os, os) {
             system_info.os = os
         }
         system_info
     }
 
+    pub fn configure(&mut self, config: &Config) {
+        self.sensors = config.sensors;
+        self.paths = config.paths.clone();
+        self.runner = Runner::from_recordings(config.commands.recorded.as_deref());
+        self.gpu_interval = Duration::from_millis(config.refresh.gpu_interval_ms);
+        self.last_gpu_poll = None;
+        self.cost_per_kwh = config.energy.cost_per_kwh;
+    }
+
+    /// Starts adding up the power draw on every update. Only the
+    /// long-running modes do, a snapshot would only add noise.
+    pub fn start_energy_meter(&mut self, settings: &EnergySettings) {
+        self.energy_meter = EnergyMeter::start(settings);
+    }
+
+    /// Saves the energy totals for the next run.
+    pub fn stop_energy_meter(&mut self) {
+        self.energy_meter = None;
+    }
+
     pub fn update(&mut self) {
-        if let Some(datetime) = get_datetime() {
+        let datetime = get_datetime(&*self.runner);
+        if let Some(datetime) = self.record(Source::Clock, datetime) {
             self.datetime = datetime
         }
-        if let Ok(gpu_info) = get_gpu_info() {
-            self.gpu_info = gpu_info;
+        if self
+            .last_gpu_poll
+            .is_none_or(|last| last.elapsed() >= self.gpu_interval)
+        {
+            self.last_gpu_poll = Some(Instant::now());
+            self.poll_gpus();
         }
 
-        if let Some(cpu_temp) = get_cpu_temp() {
+        let cpu_temp = get_cpu_temp(&self.paths, self.sensors.cpu_thermal_zone);
+        if let Some(cpu_temp) = self.record(Source::CpuTemp, cpu_temp) {
             self.cpu_temp = cpu_temp as u8
         }
 
-        if let Ok(cpu_time) = get_cpu_time() {
-            self.cpu_usage_info.update(cpu_time);
+        let cpu_times = get_cpu_times(&self.paths);
+        if let Some((cpu_time, core_times)) = self.record(Source::CpuUsage, cpu_times) {
+            self.cpu_usage_info.update(cpu_time, core_times);
             self.cpu_usage = self.cpu_usage_info.get_cpu_usage();
+            self.core_usage = self.cpu_usage_info.get_core_usage();
         }
 
-        if let Some(mem_info) = get_memory_info() {
+        let mem_info = get_memory_info(&self.paths);
+        if let Some(mem_info) = self.record(Source::Memory, mem_info) {
             self.memory_info = mem_info
         }
 
-        if let Some(cpu_freq) = get_cpu_freq() {
+        let cpu_freq = get_cpu_freq(&self.paths);
+        if let Some(cpu_freq) = self.record(Source::CpuFreq, cpu_freq) {
             self.cpu_freq = cpu_freq
         }
+
+        let rapl = get_rapl_sample(&self.paths);
+        self.cpu_power = match self.record(Source::CpuPower, rapl) {
+            Some(sample) => {
+                let power = self
+                    .last_rapl
+                    .as_ref()
+                    .and_then(|last| sample.power_since(last));
+                self.last_rapl = Some(sample);
+                power
+            }
+            None => None,
+        };
+
+        if let Some(meter) = &mut self.energy_meter {
+            let cpu = self.cpu_power.map(f64::from);
+            let gpu = self
+                .gpus
+                .iter()
+                .filter_map(|gpu| gpu.power_draw)
+                .map(f64::from)
+                .reduce(|total, watts| total + watts);
+            let watts = match (cpu, gpu) {
+                (None, None) => None,
+                _ => Some(cpu.unwrap_or_default() + gpu.unwrap_or_default()),
+            };
+            meter.add(watts);
+            self.energy = meter.reading(self.cost_per_kwh);
+        }
+    }
+
+    fn poll_gpus(&mut self) {
+        let index = self.sensors.gpu_index;
+        let gpus = get_gpus(&*self.runner)
+            .and_then(|gpus| select_gpu(&gpus, index).map(|gpu_info| (gpu_info, gpus)));
+        (self.gpu_info, self.gpus) = self.record(Source::Gpu, gpus).unwrap_or_default();
+    }
+
+    /// Keeps track of which sources are failing. Only changes are logged, a
+    /// source that stays broken would otherwise flood the log every tick.
+    fn record<T>(&mut self, source: Source, result: Result<T, CollectorError>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                if self.errors.remove(&source).is_some() {
+                    info!("{} is available again", source);
+                }
+                Some(value)
+            }
+            Err(e) => {
+                if self.errors.get(&source) != Some(&e) {
+                    warn!("{} unavailable: {}", source, e);
+                    self.errors.insert(source, e);
+                }
+                None
+            }
+        }
     }
-}
 
-fn get_cpu_time() -> Result<CPUTime, Box<dyn Error>> {
-    if let Ok(lines) = read_to_string("/proc/stat") {
-        let output = lines
-            .split('\n')
-            .next()
-            .unwrap()
-            .replace("cpu ", "")
-            .trim()
-            .replace(" ", ",");
-        let mut rdr = ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(output.as_bytes());
-        let mut iter = rdr.deserialize();
-        if let Some(result) = iter.next() {
-            let cpu_time: CPUTime = result?;
-            return Ok(cpu_time);
+    pub fn avg_cpu_freq(&self) -> f32 {
+        if self.cpu_freq.is_empty() {
+            0.
         } else {
-            return Ok(CPUTime::default());
+            self.cpu_freq.iter().sum::<f32>() / self.cpu_freq.len() as f32
         }
     }
-    Ok(CPUTime::default())
+
+    pub fn max_cpu_freq(&self) -> f32 {
+        self.cpu_freq
+            .iter()
+            .copied()
+            .map(FloatOrd)
+            .max()
+            .map(|FloatOrd(freq)| freq)
+            .unwrap_or_default()
+    }
+
+    /// The latest cumulative times of each core, in /proc/stat order.
+    pub fn core_times(&self) -> &[CPUTime] {
+        &self.cpu_usage_info.new_cores
+    }
+
+    /// One "source unavailable: reason" line for each failing source in
+    /// `sources`.
+    pub fn problems(&self, sources: &[Source]) -> Vec<String> {
+        sources
+            .iter()
+            .filter_map(|source| {
+                self.errors
+                    .get(source)
+                    .map(|e| format!("{} unavailable: {}", source, e))
+            })
+            .collect()
+    }
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> Result<String, CollectorError> {
+    read_to_string(path.as_ref()).map_err(|e| CollectorError::unreadable(path.as_ref(), e))
+}
+
+/// The aggregate `cpu` line of /proc/stat, followed by each `cpuN` line.
+fn get_cpu_times(paths: &HostPaths) -> Result<(CPUTime, Vec<CPUTime>), CollectorError> {
+    let lines = read_file(paths.stat())?;
+    let total = lines
+        .lines()
+        .find_map(|line| line.strip_prefix("cpu "))
+        .ok_or_else(|| CollectorError::malformed("no aggregate cpu line in stat"))?;
+    let cores = lines
+        .lines()
+        .filter_map(|line| line.strip_prefix("cpu"))
+        .filter(|line| line.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|line| parse_cpu_time(line.trim_start_matches(|c: char| c.is_ascii_digit())))
+        .collect::<Result<Vec<CPUTime>, CollectorError>>()?;
+    Ok((parse_cpu_time(total)?, cores))
+}
+
+fn parse_cpu_time(line: &str) -> Result<CPUTime, CollectorError> {
+    let output = line.split_whitespace().collect::<Vec<_>>().join(",");
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(output.as_bytes());
+    let mut iter = rdr.deserialize();
+    match iter.next() {
+        Some(result) => Ok(result?),
+        None => Err(CollectorError::malformed("empty cpu line in stat")),
+    }
 }
 
 #[derive(Default, Copy, Clone, Deserialize, Debug)]
@@ -110,6 +306,20 @@ pub struct CPUTime {
 }
 
 impl CPUTime {
+    /// Each field under its /proc/stat name, in clock ticks.
+    pub fn modes(&self) -> [(&'static str, u64); 8] {
+        [
+            ("user", self.user),
+            ("nice", self.nice),
+            ("system", self.system),
+            ("idle", self.idle),
+            ("iowait", self.iowait),
+            ("irq", self.irq),
+            ("softirq", self.softirq),
+            ("steal", self.steal),
+        ]
+    }
+
     fn work_time(&self) -> u64 {
         self.user + self.nice + self.system + self.irq + self.softirq + self.steal
     }
@@ -123,28 +333,46 @@ impl CPUTime {
 pub struct CPUUsageInfo {
     old: CPUTime,
     new: CPUTime,
+    old_cores: Vec<CPUTime>,
+    new_cores: Vec<CPUTime>,
 }
 
 impl CPUUsageInfo {
     fn get_cpu_usage(&self) -> u64 {
-        let total = fudge(self.new.total_time(), self.old.total_time());
-        let work = fudge(self.new.work_time(), self.old.work_time());
+        usage(&self.old, &self.new)
+    }
 
-        if total == 0. {
-            0
-        } else {
-            let usage = work / total * 100.;
-            if usage > 100. {
-                100
-            } else {
-                usage as u64
-            }
-        }
+    /// A core that appeared since the last reading (hotplug) counts from
+    /// zero, like the aggregate does on the first reading.
+    fn get_core_usage(&self) -> Vec<u64> {
+        let zero = CPUTime::default();
+        self.new_cores
+            .iter()
+            .enumerate()
+            .map(|(i, new)| usage(self.old_cores.get(i).unwrap_or(&zero), new))
+            .collect()
     }
 
-    fn update(&mut self, cpu_time: CPUTime) {
+    fn update(&mut self, cpu_time: CPUTime, core_times: Vec<CPUTime>) {
         self.old = self.new;
         self.new = cpu_time;
+        self.old_cores = std::mem::replace(&mut self.new_cores, core_times);
+    }
+}
+
+fn usage(old: &CPUTime, new: &CPUTime) -> u64 {
+    let total = fudge(new.total_time(), old.total_time());
+    let work = fudge(new.work_time(), old.work_time());
+
+    if total == 0. {
+        0
+    } else {
+        let usage = work / total * 100.;
+        if usage > 100. {
+            100
+        } else {
+            usage as u64
+        }
     }
 }
 
@@ -156,181 +384,522 @@ fn fudge(a: u64, b: u64) -> f32 {
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq, Default)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Default, Clone)]
 pub struct GPUInfo {
     pub name: String,
-    #[serde(rename = "temperature.gpu")]
-    pub(crate) temperature: u8,
-    #[serde(rename = "utilization.gpu [%]")]
-    pub utilization: u8,
-    #[serde(rename = "memory.total [MiB]")]
-    pub total_memory: u32,
-    #[serde(rename = "memory.used [MiB]")]
-    pub used_memory: u32,
-    #[serde(rename = "power.draw [W]")]
-    pub power_draw: f32,
-    #[serde(rename = "power.limit [W]")]
-    pub power_limit: f32,
-}
-
-fn get_gpu_info() -> Result<GPUInfo, Box<dyn Error>> {
+    pub(crate) temperature: Option<u8>,
+    pub utilization: Option<u8>,
+    pub total_memory: Option<u32>,
+    pub used_memory: Option<u32>,
+    pub power_draw: Option<f32>,
+    pub power_limit: Option<f32>,
+}
+
+/// One row of `nvidia-smi --format=csv,nounits`.
+#[derive(Deserialize)]
+struct NvidiaSmiRow {
+    name: String,
+    #[serde(rename = "temperature.gpu", deserialize_with = "tolerant")]
+    temperature: Option<u8>,
+    #[serde(rename = "utilization.gpu [%]", deserialize_with = "tolerant")]
+    utilization: Option<u8>,
+    #[serde(rename = "memory.total [MiB]", deserialize_with = "tolerant")]
+    total_memory: Option<u32>,
+    #[serde(rename = "memory.used [MiB]", deserialize_with = "tolerant")]
+    used_memory: Option<u32>,
+    #[serde(rename = "power.draw [W]", deserialize_with = "tolerant")]
+    power_draw: Option<f32>,
+    #[serde(rename = "power.limit [W]", deserialize_with = "tolerant")]
+    power_limit: Option<f32>,
+}
+
+impl From<NvidiaSmiRow> for GPUInfo {
+    fn from(row: NvidiaSmiRow) -> Self {
+        Self {
+            name: row.name,
+            temperature: row.temperature,
+            utilization: row.utilization,
+            total_memory: row.total_memory,
+            used_memory: row.used_memory,
+            power_draw: row.power_draw,
+            power_limit: row.power_limit,
+        }
+    }
+}
+
+/// nvidia-smi reports fields a card can't measure as `[N/A]`,
+/// `[Not Supported]` and the like. Those, and anything else that doesn't
+/// parse, become `None` so the remaining fields are still usable.
+fn tolerant<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+{
+    let raw = String::deserialize(deserializer)?;
+    Ok(raw.trim().parse::<T>().ok())
+}
+
+/// Every card `nvidia-smi` lists, in its order.
+fn get_gpus(runner: &dyn CommandRunner) -> Result<Vec<GPUInfo>, CollectorError> {
     let args = [
         "--query-gpu=name,temperature.gpu,utilization.gpu,memory.total,memory.used,power.draw,power.limit",
         "--format=csv,nounits",
     ];
-    if let Some(data) = get_command_output("nvidia-smi", Some(&args)) {
-        let data = data.replace(", ", ",");
-        let mut rdr = Reader::from_reader(data.as_bytes());
-        let mut iter = rdr.deserialize();
-        if let Some(result) = iter.next() {
-            let gpu_info: GPUInfo = result?;
-
-            Ok(gpu_info)
-        } else {
-            Ok(GPUInfo::default())
-        }
-    } else {
-        Ok(GPUInfo::default())
+    let data = runner.run("nvidia-smi", &args)?.replace(", ", ",");
+    let mut rdr = Reader::from_reader(data.as_bytes());
+    let gpus = rdr
+        .deserialize::<NvidiaSmiRow>()
+        .map(|row| row.map(GPUInfo::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    if gpus.is_empty() {
+        return Err(CollectorError::malformed("nvidia-smi reported no GPUs"));
     }
+    Ok(gpus)
+}
+
+fn select_gpu(gpus: &[GPUInfo], index: u32) -> Result<GPUInfo, CollectorError> {
+    gpus.get(index as usize).cloned().ok_or_else(|| {
+        CollectorError::malformed(format!("no GPU {}, nvidia-smi lists {}", index, gpus.len()))
+    })
 }
 
-fn get_cpu_name() -> Option<String> {
-    get_command_output("lscpu", None).and_then(parse_cpu_name)
+fn get_cpu_name(runner: &dyn CommandRunner) -> Result<String, CollectorError> {
+    runner
+        .run("lscpu", &[])
+        .and_then(|output| parse_cpu_name(&output))
 }
-fn get_cpu_freq() -> Option<Vec<f32>> {
-    read_to_string("/proc/cpuinfo")
-        .and_then(parse_cpu_freq)
-        .ok()
+
+fn get_cpu_freq(paths: &HostPaths) -> Result<Vec<f32>, CollectorError> {
+    read_file(paths.cpuinfo()).and_then(|output| parse_cpu_freq(&output))
 }
 
-fn get_cpu_temp() -> Option<f32> {
-    read_to_string("/sys/class/thermal/thermal_zone0/temp")
-        .map(|output| output.trim().parse::<f32>().unwrap_or_default() / 1000.)
-        .ok()
+fn get_cpu_temp(paths: &HostPaths, zone: u32) -> Result<f32, CollectorError> {
+    let path = paths.thermal_zone_temp(zone);
+    let output = read_file(&path)?;
+    output
+        .trim()
+        .parse::<f32>()
+        .map(|millidegrees| millidegrees / 1000.)
+        .map_err(|e| CollectorError::malformed(format!("{}: {}", path.display(), e)))
 }
 
-fn parse_cpu_name(cpu_data: String) -> Option<String> {
+fn parse_cpu_name(cpu_data: &str) -> Result<String, CollectorError> {
     let re = Regex::new(r"Model name:.*?\n").unwrap();
-    let cpu = re.find(&cpu_data).unwrap().as_str();
+    let cpu = re
+        .find(cpu_data)
+        .ok_or_else(|| CollectorError::malformed("no model name in lscpu output"))?
+        .as_str();
     let cpu = cpu.replace("Model name:", "");
-    Some(cpu.trim().to_string())
+    Ok(cpu.trim().to_string())
 }
 
-fn parse_cpu_freq(cpu_data: String) -> Result<Vec<f32>, std::io::Error> {
-    let re = Regex::new(r"cpu MHz.*?\n").unwrap();
-    let freqs = re.find_iter(&cpu_data);
-    let mut cpu_freqs = freqs
-        .into_iter()
-        .map(|m| {
-            m.as_str()
-                .replace("cpu MHz\t\t:", "")
-                .trim()
+fn parse_cpu_freq(cpu_data: &str) -> Result<Vec<f32>, CollectorError> {
+    let re = Regex::new(r"cpu MHz\s*:(.*)").unwrap();
+    let cpu_freqs = re
+        .captures_iter(cpu_data)
+        .map(|c| {
+            c[1].trim()
                 .parse::<f32>()
-                .unwrap()
+                .map_err(|e| CollectorError::malformed(format!("bad cpu MHz '{}': {}", &c[1], e)))
         })
-        .collect::<Vec<f32>>();
-    float_ord::sort(&mut cpu_freqs);
+        .collect::<Result<Vec<f32>, CollectorError>>()?;
+    if cpu_freqs.is_empty() {
+        return Err(CollectorError::malformed("no cpu MHz in cpuinfo"));
+    }
     Ok(cpu_freqs)
 }
 
-fn get_os() -> Option<String> {
-    read_to_string("/etc/os-release")
-        .map(|s| {
-            s.replace("=", "=\"")
-                .replace("\n", "\"\n")
-                .replace("\"\"", "\"")
-                .parse::<Value>()
-                .unwrap()["PRETTY_NAME"]
+fn get_os(paths: &HostPaths) -> Result<String, CollectorError> {
+    read_file(paths.os_release()).and_then(|output| parse_os_release(&output))
+}
+
+fn parse_os_release(os_release: &str) -> Result<String, CollectorError> {
+    os_release
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+        .map(|name| {
+            name.trim()
+                .trim_matches(|c| c == '"' || c == '\'')
                 .to_string()
-                .replace("\"", "")
         })
-        .ok()
-
-    // if let Ok(output) = read_to_string("/etc/os-release").map(|s| {
-    //     s.replace("=", "=\"")
-    //         .replace("\n", "\"\n")
-    //         .replace("\"\"", "\"")
-    //         .parse::<Value>()
-    //         .unwrap()["PRETTY_NAME"]
-    //         .to_string()
-    //         .replace("\"", "")
-    // }) {
-    //     Some(output)
-    // } else {
-    //     None
-    // }
+        .ok_or_else(|| CollectorError::malformed("no PRETTY_NAME in os-release"))
 }
 
-#[derive(Default)]
+/// Cumulative energy of each CPU package, in microjoules, and the value
+/// each counter wraps around at.
+struct RaplSample {
+    at: Instant,
+    packages: Vec<(u64, u64)>,
+}
+
+impl RaplSample {
+    /// `None` when packages came or went in between.
+    fn power_since(&self, last: &RaplSample) -> Option<f32> {
+        let seconds = self.at.duration_since(last.at).as_secs_f64();
+        if self.packages.is_empty() || self.packages.len() != last.packages.len() || seconds <= 0. {
+            return None;
+        }
+        let microjoules: u64 = self
+            .packages
+            .iter()
+            .zip(&last.packages)
+            .map(|(&(energy, range), &(last_energy, _))| {
+                if energy >= last_energy {
+                    energy - last_energy
+                } else {
+                    energy + range.saturating_sub(last_energy)
+                }
+            })
+            .sum();
+        Some((microjoules as f64 / 1e6 / seconds) as f32)
+    }
+}
+
+/// The top-level `intel-rapl:N` zones, one per CPU package. AMD's show up
+/// under the same name. Their subzones (`intel-rapl:N:M`) are parts of
+/// the package and would count twice.
+fn get_rapl_sample(paths: &HostPaths) -> Result<RaplSample, CollectorError> {
+    let dir = paths.powercap();
+    let at = Instant::now();
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        // No RAPL here, nothing to report.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(RaplSample {
+                at,
+                packages: vec![],
+            })
+        }
+        Err(e) => return Err(CollectorError::unreadable(&dir, e)),
+    };
+    let mut zones = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("intel-rapl:"))
+                .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+        })
+        .collect::<Vec<_>>();
+    zones.sort();
+    let packages = zones
+        .iter()
+        .map(|zone| {
+            let read = |name| {
+                let path = zone.join(name);
+                read_file(&path)?.trim().parse::<u64>().map_err(|_| {
+                    CollectorError::malformed(format!("{} is not a number", path.display()))
+                })
+            };
+            Ok((read("energy_uj")?, read("max_energy_range_uj")?))
+        })
+        .collect::<Result<_, CollectorError>>()?;
+    Ok(RaplSample { at, packages })
+}
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct MemInfo {
+    #[serde(rename = "total_kb")]
     pub(crate) total: u32,
-    available: u32,
+    #[serde(rename = "available_kb")]
+    pub(crate) available: u32,
 }
 
 impl MemInfo {
     pub(crate) fn used_mib(&self) -> u32 {
-        (self.total - self.available) / 1049
+        Bytes::from_kib(self.total.saturating_sub(self.available) as f64).mib() as u32
     }
     pub fn total_mib(&self) -> u32 {
-        self.total / 1049
+        Bytes::from_kib(self.total as f64).mib() as u32
     }
 }
 
-fn get_memory_info() -> Option<MemInfo> {
-    if let Ok(lines) = read_to_string("/proc/meminfo") {
-        let mems = lines
-            .replace("kB", "")
-            .replace("\n", ",")
-            .replace("MemTotal:", "")
-            .replace("MemFree:", "")
-            .replace("MemAvailable:", "")
-            .split(',')
-            .enumerate()
-            .take_while(|&(i, _)| i < 3)
-            .map(|(_, s)| s.trim().parse::<u32>().unwrap())
-            .collect::<Vec<u32>>();
-        let mem_info = MemInfo {
-            total: mems[0],
-            available: mems[2],
-        };
-        Some(mem_info)
-    } else {
-        None
-    }
+fn get_memory_info(paths: &HostPaths) -> Result<MemInfo, CollectorError> {
+    read_file(paths.meminfo()).and_then(|output| parse_meminfo(&output))
 }
 
-fn get_datetime() -> Option<String> {
-    get_command_output("date", None)
+fn parse_meminfo(meminfo: &str) -> Result<MemInfo, CollectorError> {
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| {
+                value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+            })
+            .ok_or_else(|| CollectorError::malformed(format!("no {} in meminfo", name)))
+    };
+    Ok(MemInfo {
+        total: field("MemTotal:")?,
+        available: field("MemAvailable:")?,
+    })
 }
 
-fn get_user() -> Option<String> {
-    get_command_output("whoami", None)
+fn get_datetime(runner: &dyn CommandRunner) -> Result<String, CollectorError> {
+    runner.run("date", &[])
 }
 
-fn get_host() -> Option<String> {
-    get_command_output("hostname", None)
+fn get_user(runner: &dyn CommandRunner) -> Result<String, CollectorError> {
+    runner.run("whoami", &[])
 }
 
-fn get_command_output(command: &str, args: Option<&[&str]>) -> Option<String> {
-    let mut c = Command::new(command);
-    if let Some(arguments) = args {
-        c.args(arguments);
+fn get_host(runner: &dyn CommandRunner) -> Result<String, CollectorError> {
+    runner.run("hostname", &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> HostPaths {
+        HostPaths::rooted(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("fixtures")
+                .join(name),
+        )
     }
-    if let Ok(output) = c.output() {
-        let mut raw = output.stdout.to_string();
-        trim_newline(&mut raw);
-        Some(raw)
-    } else {
-        None
+
+    fn recordings(name: &str) -> Runner {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/commands")
+            .join(name);
+        Runner::from_recordings(Some(&dir))
     }
-}
 
-pub trait Stringify {
-    fn to_string(&self) -> String;
-}
+    fn get_gpu_info(runner: &dyn CommandRunner, index: u32) -> Result<GPUInfo, CollectorError> {
+        get_gpus(runner).and_then(|gpus| select_gpu(&gpus, index))
+    }
+
+    fn user_time(time: &CPUTime) -> u64 {
+        time.modes()[0].1
+    }
+
+    #[test]
+    fn ubuntu() {
+        let paths = fixture("ubuntu-20.04-5.4");
+        let memory = get_memory_info(&paths).unwrap();
+        assert_eq!((memory.total, memory.available), (16314400, 11563976));
+        assert_eq!(
+            get_cpu_freq(&paths).unwrap(),
+            vec![3600., 3591.216, 4012.883, 3598.472]
+        );
+        let (total, cores) = get_cpu_times(&paths).unwrap();
+        assert_eq!(
+            total.modes()[..4],
+            [
+                ("user", 2255341),
+                ("nice", 1143),
+                ("system", 654213),
+                ("idle", 41022087)
+            ]
+        );
+        assert_eq!(cores.len(), 4);
+        assert_eq!(get_cpu_temp(&paths, 0).unwrap(), 47.);
+        assert_eq!(get_os(&paths).unwrap(), "Ubuntu 20.04.2 LTS");
+    }
+
+    #[test]
+    fn fedora_without_thermal_zone() {
+        let paths = fixture("fedora-34-5.12");
+        let memory = get_memory_info(&paths).unwrap();
+        assert_eq!((memory.total, memory.available), (32795876, 26122336));
+        let freqs = get_cpu_freq(&paths).unwrap();
+        assert_eq!(freqs.len(), 8);
+        assert_eq!((freqs[0], freqs[3]), (2200., 4100.552));
+        let (total, cores) = get_cpu_times(&paths).unwrap();
+        assert_eq!(user_time(&total), 8812301);
+        assert_eq!(cores.len(), 8);
+        assert!(get_cpu_temp(&paths, 0).is_err());
+        assert_eq!(get_os(&paths).unwrap(), "Fedora 34 (Workstation Edition)");
+    }
+
+    #[test]
+    fn centos() {
+        let paths = fixture("centos-7-3.10");
+        let memory = get_memory_info(&paths).unwrap();
+        assert_eq!((memory.total, memory.available), (3880180, 1402100));
+        assert_eq!(get_cpu_freq(&paths).unwrap(), vec![2394.374, 2394.374]);
+        let (total, cores) = get_cpu_times(&paths).unwrap();
+        assert_eq!(user_time(&total), 98231120);
+        assert_eq!(cores.len(), 2);
+        assert_eq!(get_cpu_temp(&paths, 0).unwrap(), 27.8);
+        assert_eq!(get_cpu_temp(&paths, 1).unwrap(), 51.);
+        assert_eq!(get_os(&paths).unwrap(), "CentOS Linux 7 (Core)");
+    }
+
+    #[test]
+    fn raspios_without_cpu_mhz() {
+        let paths = fixture("raspios-arm64-5.10");
+        let memory = get_memory_info(&paths).unwrap();
+        assert_eq!((memory.total, memory.available), (3919812, 3401224));
+        assert!(get_cpu_freq(&paths).is_err());
+        let (total, cores) = get_cpu_times(&paths).unwrap();
+        assert_eq!(user_time(&total), 312201);
+        assert_eq!(cores.len(), 4);
+        assert_eq!(get_cpu_temp(&paths, 0).unwrap(), 52.582);
+        // Only /usr/lib/os-release exists.
+        assert_eq!(get_os(&paths).unwrap(), "Debian GNU/Linux 11 (bullseye)");
+    }
+
+    #[test]
+    fn rtx_3090() {
+        let gpu = get_gpu_info(&*recordings("rtx-3090"), 0).unwrap();
+        assert_eq!(
+            gpu,
+            GPUInfo {
+                name: "NVIDIA GeForce RTX 3090".to_string(),
+                temperature: Some(64),
+                utilization: Some(98),
+                total_memory: Some(24268),
+                used_memory: Some(20311),
+                power_draw: Some(341.27),
+                power_limit: Some(350.),
+            }
+        );
+    }
+
+    #[test]
+    fn gtx_1080_without_power() {
+        let gpu = get_gpu_info(&*recordings("gtx-1080-power-na"), 0).unwrap();
+        assert_eq!(gpu.name, "GeForce GTX 1080");
+        assert_eq!(gpu.temperature, Some(71));
+        assert_eq!((gpu.power_draw, gpu.power_limit), (None, None));
+    }
+
+    #[test]
+    fn quadro_k2200_without_utilization() {
+        let gpu = get_gpu_info(&*recordings("quadro-k2200-not-supported"), 0).unwrap();
+        assert_eq!(gpu.name, "Quadro K2200");
+        assert_eq!(gpu.used_memory, Some(211));
+        assert_eq!(gpu.utilization, None);
+    }
+
+    #[test]
+    fn a100_picks_the_configured_index() {
+        let runner = recordings("a100-x4");
+        let utilization = (0..4)
+            .map(|index| get_gpu_info(&*runner, index).unwrap().utilization)
+            .collect::<Vec<_>>();
+        assert_eq!(utilization, vec![Some(100), Some(97), Some(100), Some(0)]);
+        assert_eq!(get_gpu_info(&*runner, 3).unwrap().power_draw, Some(52.18));
+        assert_eq!(get_gpus(&*runner).unwrap().len(), 4);
+        assert!(get_gpu_info(&*runner, 4).is_err());
+    }
 
-impl Stringify for Vec<u8> {
-    fn to_string(&self) -> String {
-        String::from_utf8_lossy(&self).parse().unwrap()
+    #[test]
+    fn no_gpu() {
+        let runner = recordings("no-gpu");
+        assert!(matches!(
+            get_gpu_info(&*runner, 0),
+            Err(CollectorError::CommandFailed(..))
+        ));
+        assert!(get_cpu_name(&*runner).is_ok());
+    }
+
+    fn smi_row(row: &str) -> GPUInfo {
+        let data = format!(
+            "name, temperature.gpu, utilization.gpu [%], memory.total [MiB], \
+             memory.used [MiB], power.draw [W], power.limit [W]\n{}",
+            row
+        )
+        .replace(", ", ",");
+        let mut rdr = Reader::from_reader(data.as_bytes());
+        let row: NvidiaSmiRow = rdr.deserialize().next().unwrap().unwrap();
+        row.into()
+    }
+
+    #[test]
+    fn power_na_leaves_the_other_fields() {
+        assert_eq!(
+            smi_row("GeForce GTX 1080, 71, 100, 8119, 7422, [N/A], [N/A]"),
+            GPUInfo {
+                name: "GeForce GTX 1080".to_string(),
+                temperature: Some(71),
+                utilization: Some(100),
+                total_memory: Some(8119),
+                used_memory: Some(7422),
+                power_draw: None,
+                power_limit: None,
+            }
+        );
+    }
+
+    #[test]
+    fn not_supported_leaves_the_other_fields() {
+        assert_eq!(
+            smi_row(
+                "Quadro K2200, 38, [Not Supported], 4043, 211, [Not Supported], [Not Supported]"
+            ),
+            GPUInfo {
+                name: "Quadro K2200".to_string(),
+                temperature: Some(38),
+                utilization: None,
+                total_memory: Some(4043),
+                used_memory: Some(211),
+                power_draw: None,
+                power_limit: None,
+            }
+        );
+    }
+
+    /// Two readings of the same packages, `seconds` apart.
+    fn rapl(seconds: u64, last: &[(u64, u64)], now: &[(u64, u64)]) -> (RaplSample, RaplSample) {
+        let at = Instant::now();
+        (
+            RaplSample {
+                at,
+                packages: last.to_vec(),
+            },
+            RaplSample {
+                at: at + Duration::from_secs(seconds),
+                packages: now.to_vec(),
+            },
+        )
+    }
+
+    #[test]
+    fn rapl_power_adds_up_the_packages() {
+        let (last, now) = rapl(
+            2,
+            &[(1_000_000, 1 << 32), (0, 1 << 32)],
+            &[(21_000_000, 1 << 32), (20_000_000, 1 << 32)],
+        );
+        assert_eq!(now.power_since(&last), Some(20.));
+    }
+
+    #[test]
+    fn rapl_power_survives_a_counter_wrapping_around() {
+        let range = 262_143_328_850;
+        let (last, now) = rapl(1, &[(range - 4_000_000, range)], &[(6_000_000, range)]);
+        assert_eq!(now.power_since(&last), Some(10.));
+    }
+
+    #[test]
+    fn rapl_power_needs_the_same_packages_and_time_passing() {
+        let (last, now) = rapl(1, &[(0, 1 << 32)], &[(1_000_000, 1 << 32), (0, 1 << 32)]);
+        assert_eq!(now.power_since(&last), None);
+        let (last, now) = rapl(0, &[(0, 1 << 32)], &[(1_000_000, 1 << 32)]);
+        assert_eq!(now.power_since(&last), None);
+        let (last, now) = rapl(1, &[], &[]);
+        assert_eq!(now.power_since(&last), None);
+    }
+
+    #[test]
+    fn used_memory_never_underflows() {
+        let memory = MemInfo {
+            total: 1024,
+            available: 4096,
+        };
+        assert_eq!(memory.used_mib(), 0);
+        let memory = MemInfo {
+            total: 16_777_216,
+            available: 4_194_304,
+        };
+        assert_eq!((memory.used_mib(), memory.total_mib()), (12_288, 16_384));
     }
 }
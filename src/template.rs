@@ -0,0 +1,524 @@
+use crate::fmt::NOT_AVAILABLE;
+use crate::metric;
+use crate::system::SystemInfo;
+use crate::units::{Bytes, Energy, Frequency, Power, Quantity, Temperature, Units, Value};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Text with `{metric.name}` placeholders, such as
+/// `"CPU {cpu.usage}% {cpu.temperature}C"`. `{{` and `}}` stand for literal
+/// braces. A placeholder may pipe the reading through filters and end with
+/// a format spec like Rust's, `{mem.used|gib:>6.1}` is the used memory in
+/// GiB, right aligned in six columns to one decimal place. Every placeholder
+/// is checked when the template is parsed, so a typo fails at config load.
+/// `{gpu.1.temperature}` and the like read one card of several, plain
+/// `gpu.*` names the one `sensors.gpu_index` picks. `temp` is short for
+/// `temperature`. `{cpu.name}` and `{gpu.name}` are text, which takes a
+/// spec but no filters, is left aligned and cut to the precision.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// A placeholder: `{metric|filter|filter:spec}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub metric: String,
+    filters: Vec<Filter>,
+    spec: Spec,
+}
+
+/// Turns a reading into the unit it's shown in. `bytes`, `power`, `freq`
+/// and `temp` pick the unit themselves, following `units`, and print its
+/// symbol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Filter {
+    /// MiB to GiB.
+    Gib,
+    /// MiB to TiB.
+    Tib,
+    /// MHz to GHz.
+    Ghz,
+    /// MiB to whichever of MiB, GiB and TiB suits.
+    Bytes,
+    /// Watts to W or kW.
+    Power,
+    /// MHz to MHz or GHz.
+    Freq,
+    /// Wh to Wh, kWh or MWh.
+    Energy,
+    /// Celsius to the configured temperature unit.
+    Temp,
+    Round,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gib" => Ok(Filter::Gib),
+            "tib" => Ok(Filter::Tib),
+            "ghz" => Ok(Filter::Ghz),
+            "bytes" => Ok(Filter::Bytes),
+            "power" => Ok(Filter::Power),
+            "freq" => Ok(Filter::Freq),
+            "energy" => Ok(Filter::Energy),
+            "temp" => Ok(Filter::Temp),
+            "round" => Ok(Filter::Round),
+            _ => Err(format!(
+                "unknown filter '{}', expected one of: gib, tib, ghz, bytes, power, freq, \
+                 energy, temp, round",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// `[[fill]align][width][.precision]`, numbers are right aligned and text
+/// left aligned unless told otherwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Spec {
+    fill: char,
+    align: Option<Align>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Default for Spec {
+    fn default() -> Self {
+        Self {
+            fill: ' ',
+            align: None,
+            width: 0,
+            precision: None,
+        }
+    }
+}
+
+impl FromStr for Spec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let align = |c| match c {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        };
+        let mut spec = Spec::default();
+        let mut rest = s;
+        let mut chars = s.chars();
+        let first = chars.next();
+        let second = chars.next();
+        if let (Some(fill), Some(a)) = (first, second.and_then(align)) {
+            spec.fill = fill;
+            spec.align = Some(a);
+            rest = &s[fill.len_utf8() + 1..];
+        } else if let Some(a) = first.and_then(align) {
+            spec.align = Some(a);
+            rest = &s[1..];
+        }
+        let (width, precision) = match rest.find('.') {
+            Some(dot) => (&rest[..dot], Some(&rest[dot + 1..])),
+            None => (rest, None),
+        };
+        let number = |digits: &str| {
+            digits
+                .parse::<usize>()
+                .map_err(|_| format!("bad format spec ':{}'", s))
+        };
+        if !width.is_empty() {
+            spec.width = number(width)?;
+        }
+        if let Some(precision) = precision {
+            spec.precision = Some(number(precision)?);
+        }
+        Ok(spec)
+    }
+}
+
+impl Spec {
+    fn pad(&self, text: &str, align: Align) -> String {
+        let missing = self.width.saturating_sub(text.chars().count());
+        let fill = |n| self.fill.to_string().repeat(n);
+        match self.align.unwrap_or(align) {
+            Align::Left => format!("{}{}", text, fill(missing)),
+            Align::Right => format!("{}{}", fill(missing), text),
+            Align::Center => format!(
+                "{}{}{}",
+                fill(missing / 2),
+                text,
+                fill(missing - missing / 2)
+            ),
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (body, spec) = match s.find(':') {
+            Some(colon) => (&s[..colon], s[colon + 1..].parse()?),
+            None => (s, Spec::default()),
+        };
+        let mut names = body.split('|').map(str::trim);
+        let metric = names.next().unwrap_or_default();
+        let text = metric::is_text(metric);
+        if !metric::is_known(metric) && !text {
+            return Err(format!(
+                "unknown metric '{}', expected one of: {}",
+                metric,
+                metric::names()
+                    .into_iter()
+                    .chain(metric::TEXT)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        let filters = names.map(str::parse).collect::<Result<Vec<_>, _>>()?;
+        if text && !filters.is_empty() {
+            return Err(format!(
+                "'{}' is text, filters only apply to numbers",
+                metric
+            ));
+        }
+        Ok(Self {
+            metric: metric.to_string(),
+            filters,
+            spec,
+        })
+    }
+}
+
+impl Field {
+    /// The field filled in from `system_info`.
+    pub fn show(&self, system_info: &SystemInfo, units: &Units) -> String {
+        if !metric::is_text(&self.metric) {
+            return self.render(metric::lookup(system_info, &self.metric), units);
+        }
+        let text = metric::lookup_text(system_info, &self.metric)
+            .unwrap_or_else(|| NOT_AVAILABLE.to_string());
+        let text = match self.spec.precision {
+            Some(precision) => text.chars().take(precision).collect(),
+            None => text,
+        };
+        self.spec.pad(&text, Align::Left)
+    }
+
+    /// The reading as the template asks for it, "n/a" if the hardware
+    /// didn't report it.
+    pub fn render(&self, value: Option<f64>, units: &Units) -> String {
+        let mut value = match value {
+            Some(value) => value,
+            None => return self.spec.pad(NOT_AVAILABLE, Align::Right),
+        };
+        let mut suffix = "";
+        for filter in &self.filters {
+            match filter {
+                Filter::Gib => value /= 1024.,
+                Filter::Tib => value /= 1024. * 1024.,
+                Filter::Ghz => value /= 1000.,
+                Filter::Bytes => (value, suffix) = Bytes::from_mib(value).scaled(units),
+                Filter::Power => (value, suffix) = Power(value).scaled(units),
+                Filter::Freq => (value, suffix) = Frequency(value).scaled(units),
+                Filter::Energy => (value, suffix) = Energy(value).scaled(units),
+                Filter::Temp => (value, suffix) = Temperature(value).scaled(units),
+                Filter::Round => value = value.round(),
+            }
+        }
+        let number = match self.spec.precision {
+            Some(precision) => format!("{:.*}", precision, value),
+            None => Value(Some(value)).to_string(),
+        };
+        self.spec.pad(&number, Align::Right) + suffix
+    }
+}
+
+impl Template {
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    pub fn render(&self, system_info: &SystemInfo, units: &Units) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(field) => field.show(system_info, units),
+            })
+            .collect()
+    }
+
+    /// This template with its `gpu.*` placeholders reading card `index`.
+    pub fn for_gpu(&self, index: usize) -> Self {
+        let parts = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                // Fields naming a card already keep it.
+                Part::Field(field)
+                    if field.metric.starts_with("gpu.")
+                        && metric::resolve(&field.metric).0.is_none() =>
+                {
+                    Part::Field(Field {
+                        metric: format!("gpu.{}.{}", index, &field.metric["gpu.".len()..]),
+                        ..field.clone()
+                    })
+                }
+                part => part.clone(),
+            })
+            .collect();
+        Self { parts }
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => {
+                                return Err(format!("unclosed '{{' in template \"{}\"", source))
+                            }
+                        }
+                    }
+                    let field = placeholder
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("{} in template \"{}\"", e, source))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => return Err(format!("unmatched '}}' in template \"{}\"", source)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl TryFrom<&str> for Template {
+    type Error = String;
+
+    fn try_from(source: &str) -> Result<Self, Self::Error> {
+        Self::try_from(source.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::GPUInfo;
+
+    fn two_cards() -> SystemInfo {
+        let mut system_info = SystemInfo::default();
+        system_info.gpus = vec![
+            GPUInfo {
+                temperature: Some(61),
+                ..GPUInfo::default()
+            },
+            GPUInfo {
+                temperature: Some(33),
+                ..GPUInfo::default()
+            },
+        ];
+        system_info
+    }
+
+    #[test]
+    fn reads_one_card_of_several() {
+        let template = Template::try_from("{gpu.0.temp:>3}C {gpu.1.temperature}C").unwrap();
+        assert_eq!(template.render(&two_cards(), &Units::default()), " 61C 33C");
+    }
+
+    #[test]
+    fn missing_card_is_not_available() {
+        let template = Template::try_from("{gpu.2.temp}").unwrap();
+        assert_eq!(template.render(&two_cards(), &Units::default()), "n/a");
+    }
+
+    #[test]
+    fn unknown_card_field_is_rejected() {
+        let e = Template::try_from("{gpu.0.fan}").unwrap_err();
+        assert!(e.starts_with("unknown metric 'gpu.0.fan'"), "{}", e);
+    }
+
+    #[test]
+    fn for_gpu_points_every_gpu_field_at_the_card() {
+        let template = Template::try_from("{cpu.usage} {gpu.temperature}").unwrap();
+        assert_eq!(
+            template.for_gpu(1),
+            Template::try_from("{cpu.usage} {gpu.1.temperature}").unwrap()
+        );
+    }
+
+    #[test]
+    fn for_gpu_keeps_fields_naming_a_card() {
+        let template = Template::try_from("{gpu.0.temp} {gpu.temp}").unwrap();
+        assert_eq!(
+            template.for_gpu(1),
+            Template::try_from("{gpu.0.temp} {gpu.1.temp}").unwrap()
+        );
+        assert_eq!(
+            template.for_gpu(1).render(&two_cards(), &Units::default()),
+            "61 33"
+        );
+    }
+
+    fn render(source: &str, value: f64) -> String {
+        let template = Template::try_from(source).unwrap();
+        match template.parts() {
+            [Part::Field(field)] => field.render(Some(value), &Units::default()),
+            parts => panic!("{:?}", parts),
+        }
+    }
+
+    #[test]
+    fn specs_pad_align_and_round() {
+        assert_eq!(render("{cpu.usage}", 12.), "12");
+        assert_eq!(render("{cpu.usage}", 12.34), "12.3");
+        assert_eq!(render("{cpu.usage:5}", 12.), "   12");
+        assert_eq!(render("{cpu.usage:<5}", 12.), "12   ");
+        assert_eq!(render("{cpu.usage:^6}", 12.), "  12  ");
+        assert_eq!(render("{cpu.usage:0>5}", 12.), "00012");
+        assert_eq!(render("{cpu.usage:*<6.2}", 1.5), "1.50**");
+        assert_eq!(render("{cpu.usage:.0}", 12.5), "12");
+        assert_eq!(render("{cpu.usage:.3}", 2.), "2.000");
+    }
+
+    #[test]
+    fn bad_specs_are_rejected() {
+        for source in ["{cpu.usage:x}", "{cpu.usage:.}", "{cpu.usage:5.x}"] {
+            let e = Template::try_from(source).unwrap_err();
+            assert!(e.starts_with("bad format spec"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(render("{mem.used|gib:.1}", 1536.), "1.5");
+        assert_eq!(render("{mem.used|tib:.2}", 1024. * 1024. * 2.), "2.00");
+        assert_eq!(render("{cpu.freq.avg|ghz:.1}", 3400.), "3.4");
+        assert_eq!(render("{mem.used|bytes}", 512.), "512 MiB");
+        assert_eq!(render("{mem.used|bytes:.1}", 6348.8), "6.2 GiB");
+        assert_eq!(render("{gpu.power.draw|power}", 1500.), "1.5 kW");
+        assert_eq!(render("{cpu.freq.avg|freq}", 999.), "999 MHz");
+        assert_eq!(render("{cpu.freq.avg|freq}", 4200.), "4.2 GHz");
+        assert_eq!(render("{energy.today|energy}", 2500.), "2.5 kWh");
+        assert_eq!(render("{cpu.temperature|temp}", 47.), "47C");
+        assert_eq!(render("{cpu.usage|round}", 12.6), "13");
+        // Padding applies to the number, the unit follows it.
+        assert_eq!(render("{gpu.power.draw|round|power:>5}", 97.4), "   97 W");
+    }
+
+    #[test]
+    fn filters_follow_units() {
+        let template = Template::try_from("{cpu.temperature|temp:.0} {mem.used|bytes}").unwrap();
+        let mut system_info = SystemInfo::default();
+        system_info.cpu_temp = 100;
+        let units: Units =
+            toml::from_str("temperature = \"fahrenheit\"\nbytes = \"decimal\"").unwrap();
+        assert!(template.render(&system_info, &units).starts_with("212F "));
+    }
+
+    #[test]
+    fn missing_readings_are_not_available() {
+        let template = Template::try_from("{cpu.power.draw|power:>5}").unwrap();
+        assert_eq!(
+            template.render(&SystemInfo::default(), &Units::default()),
+            "  n/a"
+        );
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        let template = Template::try_from("{{cpu}} {cpu.usage}%").unwrap();
+        let mut system_info = SystemInfo::default();
+        system_info.cpu_usage = 7;
+        assert_eq!(template.render(&system_info, &Units::default()), "{cpu} 7%");
+    }
+
+    #[test]
+    fn malformed_templates_are_rejected() {
+        let e = Template::try_from("{cpu.usage").unwrap_err();
+        assert!(e.starts_with("unclosed '{'"), "{}", e);
+        let e = Template::try_from("cpu}").unwrap_err();
+        assert!(e.starts_with("unmatched '}'"), "{}", e);
+        let e = Template::try_from("{cpu.usage|kelvin}").unwrap_err();
+        assert!(e.starts_with("unknown filter 'kelvin'"), "{}", e);
+        let e = Template::try_from("{cpu.load}").unwrap_err();
+        assert!(e.starts_with("unknown metric 'cpu.load'"), "{}", e);
+    }
+
+    #[test]
+    fn config_load_rejects_unknown_filters_and_metrics() {
+        for (label, error) in [
+            ("{cpu.usage|kelvin}", "unknown filter 'kelvin'"),
+            ("{cpu.load}", "unknown metric 'cpu.load'"),
+        ] {
+            let source = format!("[labels]\ncpu_usage = \"{}\"\n", label);
+            let e = toml::from_str::<crate::config::Config>(&source).unwrap_err();
+            assert!(e.to_string().contains(error), "{}", e);
+        }
+    }
+
+    #[test]
+    fn names_are_text() {
+        let mut system_info = two_cards();
+        system_info.cpu_name = " Ryzen 9 5950X\n".to_string();
+        system_info.gpus[1].name = "RTX 3090".to_string();
+        let units = Units::default();
+        let show = |source: &str| {
+            Template::try_from(source)
+                .unwrap()
+                .render(&system_info, &units)
+        };
+        assert_eq!(show("CPU : {cpu.name:16}|"), "CPU : Ryzen 9 5950X   |");
+        assert_eq!(show("{cpu.name:>8.5}"), "   Ryzen");
+        assert_eq!(show("{gpu.1.name}"), "RTX 3090");
+        assert_eq!(show("{gpu.3.name}"), "n/a");
+
+        let e = Template::try_from("{cpu.name|round}").unwrap_err();
+        assert!(e.starts_with("'cpu.name' is text"), "{}", e);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::config::{Config, Overrides, Panel};
+use crate::error::Source;
+use crate::fmt::{get_session_name, Percentify, NOT_AVAILABLE};
+use crate::headless;
+use crate::session::Recorder;
+use crate::system::SystemInfo;
+use crate::template::Template;
+use std::io::{self, Write};
+use std::path::Path;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BAR_WIDTH: usize = 30;
+
+/// Draws the dashboard in the terminal, redrawing on every refresh. GTK is
+/// never initialised, so this works over plain SSH.
+pub fn run(config_path: &Path, overrides: &Overrides, mut recorder: Option<Recorder>) {
+    let stdout = io::stdout();
+    let _screen = AlternateScreen::enter();
+    headless::run_with(config_path, overrides, |system_info, config| {
+        if let Some(recorder) = &mut recorder {
+            recorder.record(system_info);
+        }
+        let mut out = stdout.lock();
+        // Home the cursor and overwrite in place rather than clearing, which
+        // flickers.
+        let _ = write!(out, "\x1b[H{}\x1b[J", render(system_info, config));
+        let _ = out.flush();
+    });
+}
+
+/// Draws on the terminal's alternate screen with the cursor hidden, and
+/// puts back the shell's screen and cursor when dropped, including after
+/// SIGINT and SIGTERM, which stop `headless::run_with`.
+struct AlternateScreen;
+
+impl AlternateScreen {
+    fn enter() -> Self {
+        let mut out = io::stdout();
+        let _ = write!(out, "\x1b[?1049h\x1b[?25l");
+        let _ = out.flush();
+        AlternateScreen
+    }
+}
+
+impl Drop for AlternateScreen {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = write!(out, "{}\x1b[?25h\x1b[?1049l", RESET);
+        let _ = out.flush();
+    }
+}
+
+fn render(system_info: &SystemInfo, config: &Config) -> String {
+    let mut lines = vec![];
+    if config.shows(Panel::Header) {
+        header(&mut lines, system_info, config);
+    }
+    if config.shows(Panel::Cpu) {
+        cpu(&mut lines, system_info, config);
+    }
+    if config.shows(Panel::Gpu) {
+        gpu(&mut lines, system_info, config);
+    }
+    lines
+        .iter()
+        .map(|line| format!("{}\x1b[K\n", line))
+        .collect()
+}
+
+fn header(lines: &mut Vec<String>, system_info: &SystemInfo, config: &Config) {
+    let labels = &config.labels;
+    let label = |template: &Template| template.render(system_info, &config.units);
+    lines.push(format!(
+        "{}{}{}",
+        BOLD,
+        get_session_name(&system_info.user, &system_info.host),
+        RESET
+    ));
+    lines.push(system_info.os.clone());
+    lines.push(system_info.datetime.clone());
+    lines.push(label(&labels.energy));
+    if config.energy.cost_per_kwh.is_some() {
+        lines.push(label(&labels.energy_cost));
+    }
+    problems(lines, system_info, Source::HEADER);
+    lines.push(String::new());
+}
+
+fn cpu(lines: &mut Vec<String>, system_info: &SystemInfo, config: &Config) {
+    let labels = &config.labels;
+    let label = |template: &Template| template.render(system_info, &config.units);
+
+    lines.push(format!(
+        "{}{}{}",
+        BOLD,
+        label(&labels.cpu_name).trim_end(),
+        RESET
+    ));
+    lines.push(format!(
+        "  {}  {}",
+        usage_bar(Some(system_info.cpu_usage as u8)),
+        colour_temperature(Some(system_info.cpu_temp), label(&labels.cpu_temperature))
+    ));
+    lines.push(format!("  {}", label(&labels.ram_used)));
+    lines.push(format!("  {}", label(&labels.ram_total)));
+    lines.push(format!("  {}", label(&labels.cpu_freq)));
+    lines.push(format!("  {}", label(&labels.max_cpu_freq)));
+    lines.push(format!("  {}", label(&labels.cpu_power)));
+    problems(lines, system_info, Source::CPU);
+    lines.push(String::new());
+}
+
+fn gpu(lines: &mut Vec<String>, system_info: &SystemInfo, config: &Config) {
+    let gpu_info = &system_info.gpu_info;
+    let labels = &config.labels;
+    let label = |template: &Template| template.render(system_info, &config.units);
+
+    lines.push(format!(
+        "{}{}{}",
+        BOLD,
+        label(&labels.gpu_name).trim_end(),
+        RESET
+    ));
+    lines.push(format!(
+        "  {}  {}",
+        usage_bar(gpu_info.utilization),
+        colour_temperature(gpu_info.temperature, label(&labels.gpu_temperature))
+    ));
+    lines.push(format!("  {}", label(&labels.power_draw)));
+    lines.push(format!("  {}", label(&labels.power_limit)));
+    lines.push(format!("  {}", label(&labels.gpu_memory_used)));
+    lines.push(format!("  {}", label(&labels.gpu_memory_total)));
+    problems(lines, system_info, Source::GPU);
+    lines.push(String::new());
+}
+
+fn problems(lines: &mut Vec<String>, system_info: &SystemInfo, sources: &[Source]) {
+    for problem in system_info.problems(sources) {
+        lines.push(format!("  {}{}{}", RED, problem, RESET));
+    }
+}
+
+/// `[#######.......]  34%`, green below 50%, yellow below 85%, red above.
+fn usage_bar(usage: Option<u8>) -> String {
+    let usage = match usage {
+        Some(usage) => usage.min(100),
+        None => return format!("[{}] {:>4}", " ".repeat(BAR_WIDTH), NOT_AVAILABLE),
+    };
+    let filled = usage as usize * BAR_WIDTH / 100;
+    let colour = match usage {
+        0..=49 => GREEN,
+        50..=84 => YELLOW,
+        _ => RED,
+    };
+    format!(
+        "[{}{}{}{}] {:>4}",
+        colour,
+        "#".repeat(filled),
+        RESET,
+        ".".repeat(BAR_WIDTH - filled),
+        usage.as_percentage()
+    )
+}
+
+fn colour_temperature(celsius: Option<u8>, text: String) -> String {
+    let colour = match celsius {
+        Some(0..=59) => GREEN,
+        Some(60..=79) => YELLOW,
+        Some(_) => RED,
+        None => return text,
+    };
+    format!("{}{}{}", colour, text, RESET)
+}
@@ -0,0 +1,244 @@
+use crate::fmt::NOT_AVAILABLE;
+use serde::Deserialize;
+use std::fmt::{self, Display};
+
+/// How quantities are shown. Readings are always collected, stored and
+/// exported in the same units, this only changes what the views print.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Units {
+    pub temperature: TemperatureUnit,
+    pub bytes: ByteUnit,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    pub fn convert(&self, celsius: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9. / 5. + 32.,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "C",
+            TemperatureUnit::Fahrenheit => "F",
+        }
+    }
+}
+
+/// Whether a GiB is 1024 MiB or a GB is 1000 MB.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteUnit {
+    #[default]
+    Binary,
+    Decimal,
+}
+
+impl ByteUnit {
+    fn base(&self) -> f64 {
+        match self {
+            ByteUnit::Binary => 1024.,
+            ByteUnit::Decimal => 1000.,
+        }
+    }
+
+    fn symbols(&self) -> [&'static str; 5] {
+        match self {
+            ByteUnit::Binary => [" B", " KiB", " MiB", " GiB", " TiB"],
+            ByteUnit::Decimal => [" B", " kB", " MB", " GB", " TB"],
+        }
+    }
+}
+
+/// Whole numbers without a fraction, the rest to one decimal place, and
+/// "n/a" for readings the hardware didn't report.
+pub struct Value(pub Option<f64>);
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(value) if value.fract() == 0. => write!(f, "{}", value),
+            Some(value) => write!(f, "{:.1}", value),
+            None => f.write_str(NOT_AVAILABLE),
+        }
+    }
+}
+
+/// A reading in a unit picked for showing it, and the symbol to print
+/// after the number.
+pub trait Quantity {
+    fn scaled(&self, units: &Units) -> (f64, &'static str);
+}
+
+/// `quantity` in the unit it scales to, such as "6.2 GiB".
+pub fn show<Q: Quantity>(quantity: Q, units: &Units) -> String {
+    let (value, symbol) = quantity.scaled(units);
+    format!("{}{}", Value(Some(value)), symbol)
+}
+
+/// Scales `value` down by `base` until it's below `base` or the symbols
+/// run out.
+fn auto_scale(mut value: f64, base: f64, symbols: &[&'static str]) -> (f64, &'static str) {
+    let mut symbol = 0;
+    while value.abs() >= base && symbol + 1 < symbols.len() {
+        value /= base;
+        symbol += 1;
+    }
+    (value, symbols[symbol])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bytes(pub f64);
+
+impl Bytes {
+    const KIB: f64 = 1024.;
+    const MIB: f64 = 1024. * 1024.;
+
+    pub fn from_kib(kib: f64) -> Self {
+        Self(kib * Self::KIB)
+    }
+
+    pub fn from_mib(mib: f64) -> Self {
+        Self(mib * Self::MIB)
+    }
+
+    pub fn mib(&self) -> f64 {
+        self.0 / Self::MIB
+    }
+}
+
+/// Bytes, KiB, MiB, GiB or TiB, whichever keeps the number below 1024, or
+/// their decimal counterparts.
+impl Quantity for Bytes {
+    fn scaled(&self, units: &Units) -> (f64, &'static str) {
+        auto_scale(self.0, units.bytes.base(), &units.bytes.symbols())
+    }
+}
+
+/// Degrees Celsius.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Temperature(pub f64);
+
+impl Quantity for Temperature {
+    fn scaled(&self, units: &Units) -> (f64, &'static str) {
+        (
+            units.temperature.convert(self.0),
+            units.temperature.symbol(),
+        )
+    }
+}
+
+/// Watts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Power(pub f64);
+
+impl Quantity for Power {
+    fn scaled(&self, _: &Units) -> (f64, &'static str) {
+        auto_scale(self.0, 1000., &[" W", " kW"])
+    }
+}
+
+/// Watt-hours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Energy(pub f64);
+
+impl Quantity for Energy {
+    fn scaled(&self, _: &Units) -> (f64, &'static str) {
+        auto_scale(self.0, 1000., &[" Wh", " kWh", " MWh"])
+    }
+}
+
+/// Megahertz.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frequency(pub f64);
+
+impl Quantity for Frequency {
+    fn scaled(&self, _: &Units) -> (f64, &'static str) {
+        auto_scale(self.0, 1000., &[" MHz", " GHz"])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal() -> Units {
+        Units {
+            bytes: ByteUnit::Decimal,
+            ..Units::default()
+        }
+    }
+
+    #[test]
+    fn kib_to_mib_divides_by_1024() {
+        assert_eq!(Bytes::from_kib(1024.).mib(), 1.);
+        assert_eq!(Bytes::from_kib(16_777_216.).mib(), 16_384.);
+        assert_eq!(Bytes::from_mib(3.5).mib(), 3.5);
+    }
+
+    #[test]
+    fn celsius_to_fahrenheit() {
+        let fahrenheit = TemperatureUnit::Fahrenheit;
+        assert_eq!(fahrenheit.convert(0.), 32.);
+        assert_eq!(fahrenheit.convert(100.), 212.);
+        assert_eq!(fahrenheit.convert(-40.), -40.);
+        assert_eq!(TemperatureUnit::Celsius.convert(61.), 61.);
+        let units = Units {
+            temperature: TemperatureUnit::Fahrenheit,
+            ..Units::default()
+        };
+        assert_eq!(Temperature(37.).scaled(&units), (98.6, "F"));
+        assert_eq!(Temperature(37.).scaled(&Units::default()), (37., "C"));
+    }
+
+    #[test]
+    fn binary_and_decimal_symbols() {
+        assert_eq!(Bytes(1536.).scaled(&Units::default()), (1.5, " KiB"));
+        assert_eq!(Bytes(1536.).scaled(&decimal()), (1.536, " kB"));
+        assert_eq!(show(Bytes::from_mib(6348.8), &Units::default()), "6.2 GiB");
+        assert_eq!(show(Bytes(2e9), &decimal()), "2 GB");
+    }
+
+    #[test]
+    fn scaling_stops_below_the_base() {
+        let units = Units::default();
+        assert_eq!(Bytes(1023.).scaled(&units), (1023., " B"));
+        assert_eq!(Bytes(1024.).scaled(&units), (1., " KiB"));
+        assert_eq!(Bytes(1000.).scaled(&decimal()), (1., " kB"));
+        assert_eq!(Power(999.).scaled(&units), (999., " W"));
+        assert_eq!(Power(1000.).scaled(&units), (1., " kW"));
+        assert_eq!(Frequency(4200.).scaled(&units), (4.2, " GHz"));
+        assert_eq!(Energy(2.5e6).scaled(&units), (2.5, " MWh"));
+    }
+
+    #[test]
+    fn negative_values_scale_like_positive_ones() {
+        assert_eq!(Bytes(-2048.).scaled(&Units::default()), (-2., " KiB"));
+        assert_eq!(Power(-1500.).scaled(&Units::default()), (-1.5, " kW"));
+    }
+
+    #[test]
+    fn scaling_caps_at_the_largest_symbol() {
+        let pib = 1024f64.powi(5);
+        assert_eq!(Bytes(5. * pib).scaled(&Units::default()), (5120., " TiB"));
+        assert_eq!(Bytes(5e15).scaled(&decimal()), (5000., " TB"));
+        assert_eq!(Power(3e6).scaled(&Units::default()), (3000., " kW"));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(Value(Some(12.)).to_string(), "12");
+        assert_eq!(Value(Some(12.34)).to_string(), "12.3");
+        assert_eq!(Value(None).to_string(), NOT_AVAILABLE);
+    }
+}